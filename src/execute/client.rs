@@ -0,0 +1,404 @@
+// This file is part of alixt.
+// Copyright (C) 2025 Devon Harley Offutt
+//
+// alixt is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Builds the HTTP client every request is sent with.
+//!
+//! Proxies are resolved here instead of by reqwest, so the order is explicit: the command line,
+//! then the `[proxy]` table, then the standard `HTTP_PROXY`, `HTTPS_PROXY`, `ALL_PROXY` and
+//! `NO_PROXY` environment variables (upper or lower case), unless the table sets `use_env = false`.
+
+use std::{
+    collections::{HashMap, hash_map::Entry},
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use reqwest::{Certificate, Client, Identity, NoProxy, Proxy};
+
+use crate::models::{
+    cli::Args,
+    config,
+    context::Global,
+    error::AlixtError,
+    plan::{ClientSettings, ExecuteRequest, RunPlan, TestPlan},
+};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProxySettings {
+    // used for every scheme
+    pub all: Option<String>,
+    // only from the environment, where http and https can differ
+    pub http: Option<String>,
+    pub https: Option<String>,
+    pub no_proxy: Option<String>,
+    pub auth: Option<(String, String)>,
+}
+
+impl ProxySettings {
+    pub fn resolve(
+        args: &Args,
+        proxy: Option<&config::Proxy>,
+        global: &Global,
+    ) -> Result<Self, AlixtError> {
+        Self::from_sources(args, proxy, global, |key| std::env::var(key).ok())
+    }
+
+    fn from_sources(
+        args: &Args,
+        proxy: Option<&config::Proxy>,
+        global: &Global,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, AlixtError> {
+        let env = |key: &str| {
+            env(key)
+                .or_else(|| env(&key.to_lowercase()))
+                .filter(|value| !value.is_empty())
+        };
+        let resolve = |value: &String| global.substitute_values_in_text(value);
+        let mut settings = ProxySettings {
+            all: args.proxy.clone().or_else(|| proxy?.url.as_ref().map(resolve)),
+            no_proxy: args
+                .no_proxy
+                .clone()
+                .or_else(|| Some(proxy?.no_proxy.as_ref()?.join(","))),
+            ..Default::default()
+        };
+
+        if proxy.and_then(|proxy| proxy.use_env).unwrap_or(true) {
+            if settings.all.is_none() {
+                settings.all = env("ALL_PROXY");
+                settings.http = env("HTTP_PROXY");
+                settings.https = env("HTTPS_PROXY");
+            }
+            if settings.no_proxy.is_none() {
+                settings.no_proxy = env("NO_PROXY");
+            }
+        }
+
+        settings.auth = if let Some(user) = &args.proxy_user {
+            let Some((username, password)) = user.split_once(':') else {
+                return Err(AlixtError::Config(
+                    "--proxy-user must be written as <user>:<password>".to_string(),
+                ));
+            };
+            Some((username.to_string(), password.to_string()))
+        } else if let Some(proxy) = proxy
+            && let Some(username) = &proxy.username
+        {
+            let password = proxy.password.as_ref().map(resolve).unwrap_or_default();
+            Some((resolve(username), password))
+        } else {
+            None
+        };
+        Ok(settings)
+    }
+
+    /// The proxy an https request to `host` goes through, the same one reqwest picks
+    pub fn for_https(&self, host: &str) -> Option<&str> {
+        let proxy = self.https.as_ref().or(self.all.as_ref())?;
+        let bypassed = self
+            .no_proxy
+            .as_deref()
+            .is_some_and(|list| no_proxy_matches(list, host));
+        (!bypassed).then_some(proxy.as_str())
+    }
+
+    fn proxies(&self) -> Result<Vec<Proxy>, AlixtError> {
+        let mut proxies = Vec::new();
+        // more specific proxies go first, reqwest uses the first one that matches
+        if let Some(url) = &self.http {
+            proxies.push(Proxy::http(url)?);
+        }
+        if let Some(url) = &self.https {
+            proxies.push(Proxy::https(url)?);
+        }
+        if let Some(url) = &self.all {
+            proxies.push(Proxy::all(url)?);
+        }
+        Ok(proxies
+            .into_iter()
+            .map(|mut proxy| {
+                if let Some((username, password)) = &self.auth {
+                    proxy = proxy.basic_auth(username, password);
+                }
+                proxy.no_proxy(self.no_proxy.as_deref().and_then(NoProxy::from_string))
+            })
+            .collect())
+    }
+}
+
+/// Whether `host` is in a `NO_PROXY` list, read as reqwest does: `*` matches every host, IP
+/// addresses can have a prefix length, and a domain also covers its subdomains
+fn no_proxy_matches(list: &str, host: &str) -> bool {
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let ip = host.parse::<IpAddr>().ok();
+    list.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            if entry == "*" {
+                return true;
+            }
+            if let Some(ip) = ip {
+                let (network, prefix) = match entry.split_once('/') {
+                    Some((network, prefix)) => (network, prefix.parse::<u32>().ok()),
+                    None => (entry, None),
+                };
+                return network
+                    .parse::<IpAddr>()
+                    .is_ok_and(|network| in_network(ip, network, prefix));
+            }
+            let domain = entry.trim_start_matches('.').to_ascii_lowercase();
+            let host = host.to_ascii_lowercase();
+            host == domain || host.ends_with(&format!(".{domain}"))
+        })
+}
+
+fn in_network(ip: IpAddr, network: IpAddr, prefix: Option<u32>) -> bool {
+    let (ip, network, bits) = match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => (u32::from(ip) as u128, u32::from(network) as u128, 32),
+        (IpAddr::V6(ip), IpAddr::V6(network)) => (u128::from(ip), u128::from(network), 128),
+        _ => return false,
+    };
+    let shift = bits - prefix.unwrap_or(bits).min(bits);
+    ip.checked_shr(shift).unwrap_or(0) == network.checked_shr(shift).unwrap_or(0)
+}
+
+/// Settings shared by every client
+pub struct ClientOptions {
+    pub insecure: bool,
+    pub proxy: ProxySettings,
+    // trusted by every client, in addition to the ca_cert of the config
+    pub ca_cert: Option<PathBuf>,
+}
+
+/// One client per distinct set of client settings in the plan
+pub struct Clients {
+    clients: HashMap<ClientSettings, Client>,
+    proxy: Arc<ProxySettings>,
+}
+
+impl Clients {
+    pub fn build(plan: &TestPlan, options: &ClientOptions) -> Result<Self, AlixtError> {
+        let mut clients = HashMap::new();
+        let capture_requests = plan
+            .capture
+            .iter()
+            .flat_map(|capture| capture.requests.iter().flatten())
+            .map(|step| &step.request);
+        let mut needed = settings_for(&plan.client, capture_requests);
+        for run in plan.setup.iter().chain(&plan.runs).chain(&plan.teardown) {
+            needed.extend(settings_for(&run.client, run.requests.iter().chain(&run.teardown)));
+        }
+        for settings in needed {
+            if let Entry::Vacant(entry) = clients.entry(settings) {
+                let client = build_client(options, entry.key())?;
+                entry.insert(client);
+            }
+        }
+        Ok(Self {
+            clients,
+            proxy: Arc::new(options.proxy.clone()),
+        })
+    }
+
+    pub fn get(&self, settings: &ClientSettings) -> Result<&Client, AlixtError> {
+        self.clients.get(settings).ok_or_else(|| {
+            AlixtError::InternalError(format!("no client was built for {settings:?}"))
+        })
+    }
+
+    pub fn for_run(&self, run: &RunPlan) -> Result<RunClients, AlixtError> {
+        self.select(&run.client, run.requests.iter().chain(&run.teardown))
+    }
+
+    /// The clients the requests sent with `settings` need, one per unix socket they name
+    pub fn select<'a>(
+        &self,
+        settings: &ClientSettings,
+        requests: impl Iterator<Item = &'a ExecuteRequest>,
+    ) -> Result<RunClients, AlixtError> {
+        let mut clients = HashMap::new();
+        for settings in settings_for(settings, requests) {
+            let client = self.get(&settings)?.clone();
+            clients.insert(settings.unix_socket, client);
+        }
+        Ok(RunClients {
+            clients,
+            proxy: self.proxy.clone(),
+        })
+    }
+}
+
+/// The clients of one run, a request picks the one for its unix socket
+#[derive(Clone)]
+pub struct RunClients {
+    clients: HashMap<Option<PathBuf>, Client>,
+    // for the handshakes that inspect a request's tls connection
+    pub proxy: Arc<ProxySettings>,
+}
+
+impl RunClients {
+    pub fn get(&self, unix_socket: &Option<PathBuf>) -> Result<&Client, AlixtError> {
+        self.clients.get(unix_socket).ok_or_else(|| {
+            AlixtError::InternalError(format!("no client was built for the socket {unix_socket:?}"))
+        })
+    }
+}
+
+/// `settings` itself, and a copy for every unix socket in `requests`
+fn settings_for<'a>(
+    settings: &ClientSettings,
+    requests: impl Iterator<Item = &'a ExecuteRequest>,
+) -> Vec<ClientSettings> {
+    let sockets = requests.filter_map(|request| request.unix_socket.clone());
+    std::iter::once(settings.clone())
+        .chain(sockets.map(|socket| ClientSettings {
+            unix_socket: Some(socket),
+            ..settings.clone()
+        }))
+        .collect()
+}
+
+fn build_client(options: &ClientOptions, settings: &ClientSettings) -> Result<Client, AlixtError> {
+    let tls = &settings.tls;
+    let mut builder = Client::builder()
+        .danger_accept_invalid_certs(options.insecure)
+        // redirects are followed by execute_request, so each hop can be recorded
+        .redirect(reqwest::redirect::Policy::none())
+        // the certificate of each response's connection is recorded in its outcome
+        .tls_info(true)
+        // proxies from the environment are already part of ProxySettings
+        .no_proxy();
+    for proxy in options.proxy.proxies()? {
+        builder = builder.proxy(proxy);
+    }
+    for (host, ip) in &settings.resolve {
+        // port 0 keeps the port of the URL
+        builder = builder.resolve(host, SocketAddr::new(*ip, 0));
+    }
+    if let Some(socket) = &settings.unix_socket {
+        #[cfg(unix)]
+        {
+            builder = builder.unix_socket(socket.as_path());
+        }
+        #[cfg(not(unix))]
+        return Err(AlixtError::Config(format!(
+            "unix_socket {socket:?} is only supported on unix systems"
+        )));
+    }
+
+    for path in options.ca_cert.iter().chain(&tls.ca_cert) {
+        let pem = read_pem(path)?;
+        let certificates = Certificate::from_pem_bundle(&pem).map_err(|e| {
+            AlixtError::Config(format!("Invalid CA certificate in {path:?}: {e}"))
+        })?;
+        for certificate in certificates {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+
+    if let Some(cert) = &tls.client_cert {
+        let mut pem = read_pem(cert)?;
+        if let Some(key) = &tls.client_key {
+            pem.push(b'\n');
+            pem.extend(read_pem(key)?);
+        }
+        let identity = Identity::from_pem(&pem).map_err(|e| {
+            AlixtError::Config(format!(
+                "Invalid client certificate or key in {cert:?}: {e}"
+            ))
+        })?;
+        builder = builder.identity(identity);
+    }
+    Ok(builder.build()?)
+}
+
+/// Parses a `--resolve` value, written `host:port:address` as for curl
+pub fn parse_resolve_arg(value: &str) -> Result<(String, IpAddr), AlixtError> {
+    let Some((host, port, address)) = value
+        .split_once(':')
+        .and_then(|(host, rest)| rest.split_once(':').map(|(port, address)| (host, port, address)))
+    else {
+        return Err(AlixtError::Config(format!(
+            "--resolve must be written as <host>:<port>:<address>, got '{value}'"
+        )));
+    };
+    crate::models::plan::parse_resolve_entry(&format!("{host}:{port}"), address)
+        .map_err(AlixtError::Config)
+}
+
+/// A request failed because the server's certificate is not trusted, the CLI then points to
+/// `--ca-cert`
+pub fn is_untrusted_certificate(error: &reqwest::Error) -> bool {
+    format!("{error:#?}").contains("InvalidCertificate")
+}
+
+fn read_pem(path: &PathBuf) -> Result<Vec<u8>, AlixtError> {
+    std::fs::read(path)
+        .map_err(|e| AlixtError::Config(format!("Could not read PEM file {path:?}: {e}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn test_proxy_precedence() {
+        let env = |key: &str| match key {
+            "https_proxy" => Some("http://env-https:3128".to_string()),
+            "ALL_PROXY" => Some("http://env-all:3128".to_string()),
+            "NO_PROXY" => Some("localhost".to_string()),
+            _ => None,
+        };
+        let global = Global::seeded(0);
+
+        let args = Args::parse_from(["alixt", "-f", "tests.toml"]);
+        let settings = ProxySettings::from_sources(&args, None, &global, env).unwrap();
+        assert_eq!(settings.all.as_deref(), Some("http://env-all:3128"));
+        assert_eq!(settings.https.as_deref(), Some("http://env-https:3128"));
+        assert_eq!(settings.no_proxy.as_deref(), Some("localhost"));
+
+        let proxy: config::Proxy = toml::from_str(
+            r#"
+            url = "http://config:8080"
+            no_proxy = ["internal.example.com", "10.0.0.0/8"]
+            username = "ci"
+            password = "secret"
+            "#,
+        )
+        .unwrap();
+        let settings = ProxySettings::from_sources(&args, Some(&proxy), &global, env).unwrap();
+        assert_eq!(settings.all.as_deref(), Some("http://config:8080"));
+        assert_eq!(settings.https, None);
+        assert_eq!(settings.no_proxy.as_deref(), Some("internal.example.com,10.0.0.0/8"));
+        assert_eq!(settings.auth, Some(("ci".to_string(), "secret".to_string())));
+        // the tls inspection handshake bypasses the proxy for the same hosts reqwest does
+        assert_eq!(settings.for_https("api.example.com"), Some("http://config:8080"));
+        assert_eq!(settings.for_https("API.Internal.example.com"), None);
+        assert_eq!(settings.for_https("10.20.30.40"), None);
+        assert_eq!(settings.for_https("11.0.0.1"), Some("http://config:8080"));
+
+        let args = Args::parse_from([
+            "alixt", "-f", "tests.toml", "--proxy", "http://cli:9000", "--proxy-user", "me:pw",
+        ]);
+        let settings = ProxySettings::from_sources(&args, Some(&proxy), &global, env).unwrap();
+        assert_eq!(settings.all.as_deref(), Some("http://cli:9000"));
+        assert_eq!(settings.auth, Some(("me".to_string(), "pw".to_string())));
+    }
+}
@@ -14,64 +14,458 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
-use std::{collections::HashMap, str::FromStr, sync::Arc, time::Instant};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use reqwest::{
-    Client,
-    header::{HeaderMap, HeaderName, HeaderValue},
+    Client, Method, Response, StatusCode, Url,
+    header::{
+        ACCEPT, AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, COOKIE, HeaderMap, HeaderName, HeaderValue,
+        LOCATION, PROXY_AUTHORIZATION,
+    },
 };
 use serde_json::Value;
+use tokio::task::JoinSet;
 
-use crate::models::{
-    config::Assert,
-    context::{Global, RunState},
-    error::AlixtError,
-    plan::{ExecuteRequest, RunPlan, TestPlan},
-    test_data::{AssertionOutcome, FailureType, RequestOutcome, RunData, TestData},
+use crate::{
+    execute::{
+        client::{Clients, RunClients},
+        sse, tls,
+    },
+    models::{
+        config::{Assert, CookieAssert, JsonRpc},
+        context::{Global, RunState},
+        error::AlixtError,
+        plan::{CaptureStep, ExecuteRequest, PartContent, RunPlan, TestPlan},
+        test_data::{
+            AssertionOutcome, FailureType, RedirectHop, RequestOutcome, RunData, TestData,
+        },
+    },
+    utils::{
+        cache::{self, CaptureCache},
+        condition,
+        cookies::{self, RunCookies},
+        form,
+        graphql,
+        jsonrpc,
+    },
 };
 
 pub async fn execute_test(
-    client: &Client,
+    clients: &Clients,
     plan: TestPlan,
-    global: Arc<Global>,
+    mut global: Arc<Global>,
+    capture: Option<RunData>,
+    jobs: usize,
 ) -> Result<TestData, AlixtError> {
     let mut test_outcome = TestData::new();
-    for run in plan.runs {
-        match execute_run(client, run, RunState::new(global.clone())).await {
-            Ok(outcome) => test_outcome.run_data.push(outcome),
-            Err(e) => return Err(e),
+    let mut exported = HashMap::new();
+    test_outcome.capture = capture;
+    // teardown runs no matter how the setup and test runs ended, errors are returned afterwards
+    let result = execute_setup_and_runs(
+        clients,
+        plan.setup,
+        plan.runs,
+        &mut global,
+        jobs,
+        &mut test_outcome,
+        &mut exported,
+    )
+    .await;
+
+    let mut teardown_result = Ok(());
+    for run in plan.teardown {
+        let mut state = RunState::new(global.clone());
+        state.global_exports = exported.clone();
+        let result = match clients.for_run(&run) {
+            Ok(run_clients) => execute_run(&run_clients, run, &mut state).await,
+            Err(e) => Err(e),
         };
+        match result {
+            Ok(outcome) => test_outcome.teardown.push(outcome),
+            Err(e) => teardown_result = teardown_result.and(Err(e)),
+        }
+        exported.extend(state.exported);
     }
+    result.and(teardown_result)?;
     Ok(test_outcome)
 }
 
+async fn execute_setup_and_runs(
+    clients: &Clients,
+    setup: Vec<RunPlan>,
+    runs: Vec<RunPlan>,
+    global: &mut Arc<Global>,
+    jobs: usize,
+    test_outcome: &mut TestData,
+    exported: &mut HashMap<String, String>,
+) -> Result<(), AlixtError> {
+    if test_outcome.capture.as_ref().is_some_and(RunData::has_failures) {
+        let skipped = |run: RunPlan| {
+            let mut outcome = RunData::new(run.name);
+            outcome.skipped = Some("a capture request failed".to_string());
+            outcome
+        };
+        test_outcome.setup = setup.into_iter().map(skipped).collect();
+        test_outcome.run_data = runs.into_iter().map(skipped).collect();
+        return Ok(());
+    }
+
+    let mut failed_setup: Option<String> = None;
+    let mut captured = HashMap::new();
+    for run in setup {
+        let mut state = RunState::new(global.clone());
+        state.global_exports = exported.clone();
+        let outcome = execute_run(&clients.for_run(&run)?, run, &mut state).await;
+        exported.extend(std::mem::take(&mut state.exported));
+        let outcome = outcome?;
+        if failed_setup.is_none() && outcome.has_failures() {
+            failed_setup = Some(outcome.name.clone());
+        }
+        // only what the run captured, its vars and the exports it was handed stay in the run
+        for key in std::mem::take(&mut state.captured) {
+            if let Some(value) = state.run_variables.remove(&key) {
+                captured.insert(key, value);
+            }
+        }
+        test_outcome.setup.push(outcome);
+    }
+
+    // every setup RunState is gone by now, so Global is not shared yet and setup captures can
+    // still become global variables
+    if !captured.is_empty() {
+        let Some(global) = Arc::get_mut(global) else {
+            return Err(AlixtError::InternalError(
+                "Global is still shared after the setup runs".to_string(),
+            ));
+        };
+        global.variables.extend(captured);
+    }
+
+    if let Some(setup_name) = &failed_setup {
+        for run in runs {
+            let mut outcome = RunData::new(run.name);
+            outcome.skipped = Some(format!("setup run '{setup_name}' failed"));
+            test_outcome.run_data.push(outcome);
+        }
+        return Ok(());
+    }
+    test_outcome.run_data = execute_runs(clients, runs, global, jobs, exported).await?;
+    Ok(())
+}
+
+/// Executes the runs in dependency order, with up to `jobs` of them at the same time. Outcomes
+/// are returned in the order of `runs`, whatever order they finished in, and request exports are
+/// merged into `global_exports` in that order too.
+async fn execute_runs(
+    clients: &Clients,
+    runs: Vec<RunPlan>,
+    global: &Arc<Global>,
+    jobs: usize,
+    global_exports: &mut HashMap<String, String>,
+) -> Result<Vec<RunData>, AlixtError> {
+    let count = runs.len();
+    // every RunState forks the seeded rng, so create them in plan order to stay reproducible
+    let mut states: Vec<Option<RunState>> = (0..count)
+        .map(|_| Some(RunState::new(global.clone())))
+        .collect();
+    let exports: Vec<Vec<String>> = runs.iter().map(|run| run.exports.clone()).collect();
+    let mut pending: Vec<Option<RunPlan>> = runs.into_iter().map(Some).collect();
+    let mut outcomes: Vec<Option<RunData>> = (0..count).map(|_| None).collect();
+    let mut exported: Vec<HashMap<String, String>> = vec![HashMap::new(); count];
+    let mut exported_globals: Vec<HashMap<String, String>> = vec![HashMap::new(); count];
+    let mut running = JoinSet::new();
+    // after an error no new run starts, but the ones already running finish with their teardown
+    // before it is returned, dropping the JoinSet would abort them
+    let mut first_error: Option<AlixtError> = None;
+
+    loop {
+        // start runs until nothing else can start, skipping those with a prerequisite that did
+        // not pass right away, since that can make further runs skippable
+        let mut progressed = first_error.is_none();
+        while progressed {
+            progressed = false;
+            for index in 0..count {
+                let Some(run) = &pending[index] else {
+                    continue;
+                };
+                let mut waiting = false;
+                let mut failed_dependency = None;
+                for &dependency in &run.depends_on {
+                    match &outcomes[dependency] {
+                        None => waiting = true,
+                        Some(outcome) if outcome.skipped.is_some() => {
+                            failed_dependency =
+                                Some(format!("dependency '{}' was skipped", outcome.name));
+                        }
+                        Some(outcome) if outcome.has_failures() => {
+                            failed_dependency =
+                                Some(format!("dependency '{}' failed", outcome.name));
+                        }
+                        Some(_) => {}
+                    }
+                }
+                waiting |= run.global_sources.iter().any(|&source| outcomes[source].is_none());
+
+                if let Some(reason) = failed_dependency {
+                    let mut outcome = RunData::new(run.name.clone());
+                    outcome.skipped = Some(reason);
+                    outcomes[index] = Some(outcome);
+                    pending[index] = None;
+                    progressed = true;
+                    continue;
+                }
+                if waiting || running.len() >= jobs {
+                    continue;
+                }
+
+                let (Some(run), Some(mut state)) = (pending[index].take(), states[index].take())
+                else {
+                    continue;
+                };
+                for &dependency in &run.depends_on {
+                    state.run_variables.extend(exported[dependency].clone());
+                }
+                state.global_exports = global_exports.clone();
+                for &source in &run.global_sources {
+                    state.global_exports.extend(exported_globals[source].clone());
+                }
+                let run_clients = match clients.for_run(&run) {
+                    Ok(run_clients) => run_clients,
+                    Err(e) => {
+                        first_error = Some(e);
+                        break;
+                    }
+                };
+                running.spawn(async move {
+                    let result = execute_run(&run_clients, run, &mut state).await;
+                    (index, result.map(|outcome| (outcome, state)))
+                });
+                progressed = true;
+            }
+            progressed &= first_error.is_none();
+        }
+
+        let Some(joined) = running.join_next().await else {
+            break;
+        };
+        let result = joined
+            .map_err(|e| AlixtError::InternalError(format!("run task failed: {e}")))
+            .and_then(|(index, result)| Ok((index, result?)));
+        let (index, (outcome, mut state)) = match result {
+            Ok(finished) => finished,
+            Err(e) => {
+                first_error.get_or_insert(e);
+                continue;
+            }
+        };
+        exported_globals[index] = std::mem::take(&mut state.exported);
+        exported[index] = exports[index]
+            .iter()
+            .filter_map(|key| {
+                let value = state.run_variables.get(key)?;
+                Some((key.clone(), value.clone()))
+            })
+            .collect();
+        outcomes[index] = Some(outcome);
+    }
+    if let Some(e) = first_error {
+        return Err(e);
+    }
+    for exports in exported_globals {
+        global_exports.extend(exports);
+    }
+    Ok(outcomes.into_iter().flatten().collect())
+}
+
 async fn execute_run(
-    client: &Client,
+    clients: &RunClients,
     run: RunPlan,
-    mut state: RunState,
+    state: &mut RunState,
 ) -> Result<RunData, AlixtError> {
     let mut run_outcome = RunData::new(run.name.clone());
-    for request in run.requests {
-        let outcome = execute_request(client, request, &mut state).await?;
-        if !outcome.passing.is_passing() && outcome.breaking {
-            run_outcome.outcomes.push(outcome);
-            return Ok(run_outcome);
+    if let Some(vars) = &run.vars {
+        state.add_run_variables(vars);
+    }
+    if let Some(reason) = skip_reason(&run.skip_if, &run.only_if, state) {
+        run_outcome.skipped = Some(reason);
+        return Ok(run_outcome);
+    }
+    state.client = run.client.clone();
+    if run.cookies {
+        let seeds = run
+            .seed_cookies
+            .iter()
+            .map(|(name, value)| (name.clone(), state.substitute_values_in_text(value)))
+            .collect();
+        state.cookies = Some(RunCookies::new(seeds));
+    }
+
+    let result = execute_requests(clients, run.requests, state, &mut run_outcome.outcomes).await;
+
+    // teardown requests run even after a breaking failure or an error, and never break themselves
+    let mut teardown_result = Ok(());
+    for request in run.teardown {
+        match execute_planned_request(clients, request, state).await {
+            Ok(outcome) => run_outcome.teardown.push(outcome),
+            Err(e) => teardown_result = teardown_result.and(Err(e)),
         }
-        run_outcome.outcomes.push(outcome);
     }
+    result.and(teardown_result)?;
     Ok(run_outcome)
 }
 
+async fn execute_requests(
+    clients: &RunClients,
+    requests: Vec<ExecuteRequest>,
+    state: &mut RunState,
+    outcomes: &mut Vec<RequestOutcome>,
+) -> Result<(), AlixtError> {
+    for request in requests {
+        let outcome = execute_planned_request(clients, request, state).await?;
+        if outcome.passing.is_failed() && outcome.breaking {
+            outcomes.push(outcome);
+            return Ok(());
+        }
+        outcomes.push(outcome);
+    }
+    Ok(())
+}
+
+async fn execute_planned_request(
+    clients: &RunClients,
+    request: ExecuteRequest,
+    state: &mut RunState,
+) -> Result<RequestOutcome, AlixtError> {
+    if request.foreach.is_some() {
+        execute_foreach(clients, request, state).await
+    } else {
+        execute_request(clients, request, state).await
+    }
+}
+
+/// Runs a `foreach` request once per element of its array, the parent outcome only summarizes
+/// the iterations
+async fn execute_foreach(
+    clients: &RunClients,
+    mut request: ExecuteRequest,
+    state: &mut RunState,
+) -> Result<RequestOutcome, AlixtError> {
+    let foreach = request.foreach.take().unwrap_or_default();
+    let mut outcome = RequestOutcome {
+        name: request.name.clone(),
+        method: request.method.to_string(),
+        url: request.url.clone(),
+        passing: AssertionOutcome::Passed,
+        breaking: request.assert.as_ref().is_some_and(|a| a.breaking),
+        status: None,
+        response_body: None,
+        duration: Duration::ZERO,
+        generated_values: Vec::new(),
+        iterations: Vec::new(),
+        cached: false,
+        redirects: Vec::new(),
+        final_url: None,
+        remote_addr: None,
+        tls: None,
+        events: None,
+    };
+
+    let source = if foreach.starts_with('/') {
+        state
+            .last_response
+            .as_ref()
+            .and_then(|json| json.pointer(&foreach))
+            .cloned()
+    } else {
+        state
+            .substitute_values_in_text(&format!("{{{{{foreach}}}}}"))
+            .parse::<Value>()
+            .ok()
+    };
+    let items = match source {
+        Some(Value::Array(items)) => items,
+        other => {
+            outcome.passing.push(FailureType::ForeachNotArray {
+                foreach,
+                found: other.map_or("None".to_string(), |v| v.to_string()),
+            });
+            return Ok(outcome);
+        }
+    };
+
+    let total = items.len();
+    let mut failed = 0;
+    for (index, item) in items.iter().enumerate() {
+        state.set_item(index, item);
+        let mut iteration = request.clone();
+        iteration.name = format!("{} [{index}]", request.name);
+
+        let iteration_outcome = execute_request(clients, iteration, state).await?;
+        outcome.duration += iteration_outcome.duration;
+        let stop = iteration_outcome.passing.is_failed() && iteration_outcome.breaking;
+        if iteration_outcome.passing.is_failed() {
+            failed += 1;
+        }
+        outcome.iterations.push(iteration_outcome);
+        if stop {
+            break;
+        }
+    }
+    state.clear_item();
+
+    if failed > 0 {
+        outcome
+            .passing
+            .push(FailureType::IterationsFailed { failed, total });
+    }
+    Ok(outcome)
+}
+
 async fn execute_request(
-    client: &Client,
+    clients: &RunClients,
     request: ExecuteRequest,
     state: &mut RunState,
 ) -> Result<RequestOutcome, AlixtError> {
+    state.row_variables = request.row.clone().unwrap_or_default();
+    state.request_variables.clear();
+    if let Some(vars) = &request.vars {
+        state.add_request_variables(vars);
+    }
+    if let Some(reason) = skip_reason(&request.skip_if, &request.only_if, state) {
+        return Ok(RequestOutcome {
+            name: request.name,
+            method: request.method.to_string(),
+            url: request.url,
+            passing: AssertionOutcome::Skipped { reason },
+            breaking: false,
+            status: None,
+            response_body: None,
+            duration: Duration::ZERO,
+            generated_values: std::mem::take(&mut state.generated),
+            iterations: Vec::new(),
+            cached: false,
+            redirects: Vec::new(),
+            final_url: None,
+            remote_addr: None,
+            tls: None,
+            events: None,
+        });
+    }
+
     let url = state.substitute_values_in_text(&request.url);
     let mut final_headers = HeaderMap::new();
     if let Some(headers) = &request.headers {
         for (key, value) in headers {
-            let value = state.substitute_values_in_text(value);
+            let value = if request.raw.headers {
+                value.clone()
+            } else {
+                state.substitute_values_in_text(value)
+            };
 
             let header_name = HeaderName::from_str(key).map_err(|e| {
                 AlixtError::Config(format!("Invalid Header Name '{}', {:#?}", key, e))
@@ -86,28 +480,152 @@ async fn execute_request(
             final_headers.insert(header_name, header_value);
         }
     }
-
-    let mut builder = client
-        .request(request.method.clone(), url.clone())
-        .headers(final_headers);
-
-    if let Some(text) = request.body {
-        let body = state.substitute_values_in_text(text.as_str());
-        builder = builder.body(body);
+    if let Some(content_type) = &request.content_type
+        && !final_headers.contains_key(CONTENT_TYPE)
+    {
+        let header_value = HeaderValue::from_str(content_type).map_err(|e| {
+            AlixtError::Config(format!("Invalid content_type '{}', {:#?}", content_type, e))
+        })?;
+        final_headers.insert(CONTENT_TYPE, header_value);
+    }
+    if request.sse.is_some() && !final_headers.contains_key(ACCEPT) {
+        final_headers.insert(ACCEPT, HeaderValue::from_static("text/event-stream"));
     }
 
+    // one id per call that expects a response, counting up through the run
+    let jsonrpc_ids: Vec<Option<u64>> = request
+        .jsonrpc
+        .iter()
+        .flat_map(JsonRpc::calls)
+        .map(|call| (call.notification != Some(true)).then(|| state.next_jsonrpc_id()))
+        .collect();
+    let mut substitute = |text: String| {
+        if request.raw.body {
+            text
+        } else {
+            state.substitute_values_in_text(text.as_str())
+        }
+    };
+    let body = if let Some(form) = request.form {
+        let fields: Vec<(String, String)> = form
+            .into_iter()
+            .map(|(name, value)| (name, substitute(value)))
+            .collect();
+        Some(form::encode_form(&fields))
+    } else if let Some(mut parts) = request.multipart {
+        for part in &mut parts {
+            if let PartContent::Text(text) = &mut part.content {
+                *text = substitute(std::mem::take(text));
+            }
+            part.filename = part.filename.take().map(&mut substitute);
+        }
+        // the boundary has to match the body, so this replaces a Content-Type from the headers
+        let boundary = form::boundary();
+        let header_value = HeaderValue::from_str(&format!("multipart/form-data; boundary={boundary}"))
+            .map_err(|e| AlixtError::InternalError(format!("Invalid multipart boundary: {e}")))?;
+        final_headers.insert(CONTENT_TYPE, header_value);
+        Some(form::encode_multipart(&parts, &boundary))
+    } else if let Some(operation) = request.graphql {
+        let body = graphql::request_body(operation, &mut substitute);
+        Some(serde_json::to_vec(&body)?)
+    } else if let Some(rpc) = &request.jsonrpc {
+        let body = jsonrpc::request_body(rpc, &jsonrpc_ids, &mut substitute);
+        Some(serde_json::to_vec(&body)?)
+    } else {
+        request
+            .body
+            .map(substitute)
+            .map(String::into_bytes)
+            .or(request.body_bytes)
+    };
+
     let start = Instant::now();
-    let response = builder.send().await?;
+    let sent = send_following_redirects(
+        clients.get(&request.unix_socket)?,
+        request.method.clone(),
+        &url,
+        final_headers,
+        body,
+        request.redirect_limit,
+        state,
+    );
+    // the timeout of an event stream starts with the request, not once the headers arrived
+    let sse = request
+        .sse
+        .as_ref()
+        .map(|options| (options, tokio::time::Instant::from_std(start + options.timeout)));
+    let (response, redirects) = match sse {
+        Some((options, deadline)) => match tokio::time::timeout_at(deadline, sent).await {
+            Ok(sent) => sent?,
+            Err(_) => {
+                let timeout = humantime::format_duration(options.timeout);
+                return Ok(RequestOutcome {
+                    name: request.name,
+                    method: request.method.to_string(),
+                    url,
+                    passing: AssertionOutcome::Failed(vec![FailureType::SseMismatch {
+                        check: "timeout".to_string(),
+                        expected: format!("response headers within {timeout}"),
+                        found: "no response".to_string(),
+                    }]),
+                    breaking: request.assert.as_ref().is_some_and(|assert| assert.breaking),
+                    status: None,
+                    response_body: None,
+                    duration: start.elapsed(),
+                    generated_values: std::mem::take(&mut state.generated),
+                    iterations: Vec::new(),
+                    cached: false,
+                    redirects: Vec::new(),
+                    final_url: None,
+                    remote_addr: None,
+                    tls: None,
+                    events: Some(Vec::new()),
+                });
+            }
+        },
+        None => sent.await?,
+    };
     let duration = start.elapsed();
 
     let status = response.status();
-    let body_text = response.text().await?;
+    let final_url = (!redirects.is_empty()).then(|| response.url().to_string());
+    let remote_addr = response.remote_addr();
+    let peer_certificate = response
+        .extensions()
+        .get::<reqwest::tls::TlsInfo>()
+        .and_then(|info| info.peer_certificate())
+        .map(tls::peer_summary);
+    let location = response
+        .headers()
+        .get(LOCATION)
+        .and_then(|location| location.to_str().ok())
+        .map(String::from);
+    let set_cookies = cookies::set_cookie_headers(response.headers());
+    let (body_text, event_stream) = match sse {
+        Some((options, deadline)) => {
+            let stream = sse::read_events(response, options, deadline).await?;
+            (stream.text.clone(), Some(stream))
+        }
+        None => (response.text().await?, None),
+    };
 
-    let json: Option<Value> = if !body_text.is_empty() {
+    let mut json: Option<Value> = if let Some(stream) = &event_stream {
+        Some(stream.data())
+    } else if !body_text.is_empty() {
         serde_json::from_str(&body_text).ok()
     } else {
         None
     };
+    // batch responses are put in the order of the calls before anything reads them
+    let mut jsonrpc_failures = Vec::new();
+    if let Some(rpc) = &request.jsonrpc {
+        json = jsonrpc::match_responses(rpc, &jsonrpc_ids, json, &mut jsonrpc_failures);
+    }
+
+    // a HEAD response has no body, so `foreach` keeps iterating the last one that had
+    if request.method != Method::HEAD {
+        state.last_response = json.clone();
+    }
 
     if let Some(capture) = request.capture
         && let Some(json) = &json
@@ -124,10 +642,14 @@ async fn execute_request(
                     Value::String(s) => s.clone(),
                     v => v.to_string(),
                 };
+                state.captured.insert(variable.clone());
                 state.run_variables.insert(variable, value_string);
             }
         }
     }
+    for key in &request.export {
+        state.export(key);
+    }
 
     let mut outcome = RequestOutcome {
         name: request.name,
@@ -142,29 +664,276 @@ async fn execute_request(
             None
         },
         duration,
+        generated_values: Vec::new(),
+        iterations: Vec::new(),
+        cached: false,
+        redirects,
+        final_url,
+        remote_addr,
+        tls: None,
+        events: None,
     };
+    let mut tls_failure = None;
+    match peer_certificate {
+        Some(Ok(summary)) => outcome.tls = Some(summary),
+        Some(Err(e)) => tls_failure = Some(e),
+        None => {}
+    }
 
-    if let Some(assert) = request.assert {
+    if let Some(assert) = &request.assert {
         outcome.breaking = assert.breaking;
-        outcome.passing = assert_response(json.as_ref(), &assert, outcome.status, state);
+        outcome.passing = assert_response(
+            json.as_ref(),
+            assert,
+            outcome.status,
+            request.raw.assert,
+            state,
+        );
+        if let Some(expected) = &assert.cookies {
+            let expected = substitute_cookie_values(expected, request.raw.assert, state);
+            cookies::check_cookies(&set_cookies, &expected, &mut outcome.passing);
+        }
+        assert_redirects(assert, &mut outcome, location, request.raw.assert, state);
+        let pattern = assert.graphql_error_matches.as_ref().map(|pattern| {
+            if request.raw.assert {
+                pattern.clone()
+            } else {
+                state.substitute_values_in_text(pattern)
+            }
+        });
+        graphql::check_graphql(
+            json.as_ref(),
+            assert.graphql_no_errors.unwrap_or(false),
+            pattern.as_deref(),
+            &mut outcome.passing,
+        );
+        if let Some(expected) = &assert.tls {
+            let inspected_url = outcome.final_url.as_ref().unwrap_or(&outcome.url);
+            // the protocol is only known from a handshake of our own
+            if expected.min_tls_version.is_some()
+                && let Some(summary) = &mut outcome.tls
+            {
+                match tls::inspect(
+                    inspected_url,
+                    &state.client,
+                    request.unix_socket.as_deref(),
+                    &clients.proxy,
+                )
+                .await
+                {
+                    Ok(inspected) => {
+                        summary.protocol = inspected.protocol;
+                        summary.cipher_suite = inspected.cipher_suite;
+                        // the intermediates are not kept for the request's own connection
+                        summary.chain.extend(inspected.chain.into_iter().skip(1));
+                    }
+                    Err(e) => tls_failure = Some(e),
+                }
+            }
+            match (&outcome.tls, tls_failure) {
+                (_, Some(e)) => outcome.passing.push(FailureType::TlsMismatch {
+                    check: "handshake".to_string(),
+                    expected: "an inspectable tls connection".to_string(),
+                    found: e,
+                }),
+                (Some(summary), None) => tls::check_tls(summary, expected, &mut outcome.passing),
+                (None, None) => outcome.passing.push(FailureType::TlsMismatch {
+                    check: "handshake".to_string(),
+                    expected: "a tls connection".to_string(),
+                    found: format!("'{inspected_url}' was not requested over tls"),
+                }),
+            }
+        }
     }
+    for failure in jsonrpc_failures {
+        outcome.passing.push(failure);
+    }
+    if let (Some(stream), Some(options)) = (event_stream, &request.sse) {
+        sse::check_sse(&stream, options, request.assert.as_ref(), &mut outcome.passing);
+        outcome.events = Some(stream.events);
+    }
+    outcome.generated_values = std::mem::take(&mut state.generated);
     Ok(outcome)
 }
 
+/// Sends the request, following up to `limit` redirects itself so that every hop is recorded and
+/// the run's cookie jar sees the cookies set along the way
+async fn send_following_redirects(
+    client: &Client,
+    mut method: Method,
+    url: &str,
+    mut headers: HeaderMap,
+    mut body: Option<Vec<u8>>,
+    limit: usize,
+    state: &mut RunState,
+) -> Result<(Response, Vec<RedirectHop>), AlixtError> {
+    let mut url =
+        Url::parse(url).map_err(|e| AlixtError::Config(format!("Invalid URL '{url}': {e}")))?;
+    let mut hops = Vec::new();
+    loop {
+        let mut hop_headers = headers.clone();
+        if let Some(cookies) = &mut state.cookies {
+            cookies.apply(&url, &mut hop_headers);
+        }
+        let mut builder = client
+            .request(method.clone(), url.clone())
+            .headers(hop_headers);
+        if let Some(body) = &body {
+            builder = builder.body(body.clone());
+        }
+        let response = builder.send().await?;
+        if let Some(cookies) = &state.cookies {
+            cookies.store(&url, response.headers());
+        }
+
+        let status = response.status();
+        let is_redirect = matches!(
+            status,
+            StatusCode::MOVED_PERMANENTLY
+                | StatusCode::FOUND
+                | StatusCode::SEE_OTHER
+                | StatusCode::TEMPORARY_REDIRECT
+                | StatusCode::PERMANENT_REDIRECT
+        );
+        if !is_redirect || hops.len() >= limit {
+            return Ok((response, hops));
+        }
+        let Some(next) = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .and_then(|location| url.join(location).ok())
+        else {
+            return Ok((response, hops));
+        };
+        hops.push(RedirectHop {
+            status: status.as_u16(),
+            url: url.to_string(),
+            location: next.to_string(),
+        });
+
+        // like browsers, a 303 and a 301/302 answering a POST continue as a GET without a body
+        if (status == StatusCode::SEE_OTHER && method != Method::HEAD)
+            || (matches!(status, StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND)
+                && method == Method::POST)
+        {
+            method = Method::GET;
+            body = None;
+            headers.remove(CONTENT_TYPE);
+            headers.remove(CONTENT_LENGTH);
+        }
+        // credentials are not handed to a different origin
+        if next.origin() != url.origin() {
+            headers.remove(AUTHORIZATION);
+            headers.remove(PROXY_AUTHORIZATION);
+            headers.remove(COOKIE);
+        }
+        url = next;
+    }
+}
+
+fn assert_redirects(
+    assert: &Assert,
+    outcome: &mut RequestOutcome,
+    location: Option<String>,
+    raw: bool,
+    state: &mut RunState,
+) {
+    let mut resolve = |value: &String| {
+        if raw {
+            value.clone()
+        } else {
+            state.substitute_values_in_text(value)
+        }
+    };
+    if let Some(expected) = assert.redirect_count
+        && expected != outcome.redirects.len()
+    {
+        outcome.passing.push(FailureType::RedirectCountMismatch {
+            expected,
+            found: outcome.redirects.len(),
+        });
+    }
+    if let Some(expected) = &assert.final_url {
+        let expected = resolve(expected);
+        let found = outcome.final_url.as_ref().unwrap_or(&outcome.url);
+        if !urls_match(&expected, found) {
+            outcome.passing.push(FailureType::FinalUrlMismatch {
+                expected,
+                found: found.clone(),
+            });
+        }
+    }
+    if let Some(expected) = &assert.location {
+        let expected = resolve(expected);
+        if location.as_deref() != Some(expected.as_str()) {
+            outcome.passing.push(FailureType::LocationMismatch {
+                expected,
+                found: location,
+            });
+        }
+    }
+}
+
+/// Compares URLs after normalizing them, so `http://host` matches `http://host/`
+fn urls_match(expected: &str, found: &str) -> bool {
+    match (Url::parse(expected), Url::parse(found)) {
+        (Ok(expected), Ok(found)) => expected == found,
+        _ => expected == found,
+    }
+}
+
+fn substitute_cookie_values(
+    expected: &HashMap<String, CookieAssert>,
+    raw: bool,
+    state: &mut RunState,
+) -> HashMap<String, CookieAssert> {
+    let mut expected = expected.clone();
+    if !raw {
+        for assert in expected.values_mut() {
+            if let Some(value) = &assert.value {
+                assert.value = Some(state.substitute_values_in_text(value));
+            }
+        }
+    }
+    expected
+}
+
+/// Checks the skip_if / only_if conditions of a run or request, returning why it should be
+/// skipped, if it should be
+fn skip_reason(
+    skip_if: &Option<String>,
+    only_if: &Option<String>,
+    state: &mut RunState,
+) -> Option<String> {
+    if let Some(skip_if) = skip_if
+        && condition::evaluate(skip_if, |text| state.substitute_values_in_text(text))
+    {
+        return Some(format!("skip_if was true: {skip_if}"));
+    }
+    if let Some(only_if) = only_if
+        && !condition::evaluate(only_if, |text| state.substitute_values_in_text(text))
+    {
+        return Some(format!("only_if was false: {only_if}"));
+    }
+    None
+}
+
 fn assert_response(
     body_json: Option<&Value>,
     assertions: &Assert,
     status: Option<u16>,
+    raw: bool,
     state: &mut RunState,
 ) -> AssertionOutcome {
     let mut outcome = AssertionOutcome::Passed;
-    if let Some(expected) = assertions.status {
-        if status.map_or(true, |num| num != expected) {
-            outcome.push(FailureType::StatusMismatch {
-                expected,
-                found: status,
-            })
-        }
+    if let Some(expected) = assertions.status
+        && status != Some(expected)
+    {
+        outcome.push(FailureType::StatusMismatch {
+            expected,
+            found: status,
+        })
     }
 
     // if there are no json assertions, early return
@@ -198,7 +967,11 @@ fn assert_response(
                         });
                         continue;
                     };
-                    let pattern = state.substitute_values_in_text(pattern);
+                    let pattern = if raw {
+                        pattern.to_string()
+                    } else {
+                        state.substitute_values_in_text(pattern)
+                    };
                     let Ok(re) = regex::Regex::new(pattern.as_str()) else {
                         outcome.push(FailureType::JsonRegexMismatch {
                             path: path.clone(),
@@ -221,8 +994,14 @@ fn assert_response(
                     if expected_value.as_str() == Some("*") {
                         continue;
                     } else {
-                        let expected_value = if let Value::String(expected_str) = expected_value {
-                            Value::String(state.substitute_values_in_text(expected_str.as_str()).to_string())
+                        let expected_value = if let Value::String(expected_str) = expected_value
+                            && !raw
+                        {
+                            Value::String(
+                                state
+                                    .substitute_values_in_text(expected_str.as_str())
+                                    .to_string(),
+                            )
                         } else {
                             expected_value.clone()
                         };
@@ -299,76 +1078,520 @@ fn flatten_json(root: &Value) -> Vec<String> {
     paths
 }
 
-// in place as a reminder to eventually add an initial capture run, and global_variables inside
-// Global. execute_capture_run() will almost certainly be needed, but execute_capture_request may
-// be redundant, wont know until we get there.
-#[allow(unused)]
-pub async fn execute_capture_run() {}
+/// Executes the requests of the [capture] section in their own RunState, and returns Global with
+/// everything they captured added to its variables. Unlike in a run, every capture pattern has to
+/// match, a missing one fails the request. Requests with a cache ttl reuse unexpired values from
+/// `cache` instead of being sent, and store their values there after passing.
+pub async fn execute_capture_run(
+    clients: &RunClients,
+    steps: Vec<CaptureStep>,
+    global: Global,
+    mut cache: Option<&mut CaptureCache>,
+) -> Result<(Global, RunData), AlixtError> {
+    let global = Arc::new(global);
+    let mut state = RunState::new(global.clone());
+    let mut run_outcome = RunData::new("capture".to_string());
 
-pub async fn execute_capture_request(
-    client: &Client,
-    request: &ExecuteRequest,
-    global: &mut Global,
-) -> Result<(), AlixtError> {
-    let url = global.substitute_values_in_text(&request.url);
-    let mut final_headers = HeaderMap::new();
-    if let Some(headers) = &request.headers {
-        for (key, value) in headers {
-            let value = global.substitute_values_in_text(value);
+    let result = async {
+        for CaptureStep { request, cache_ttl } in steps {
+            let capture = request.capture.clone().unwrap_or_default();
+            let key = cache_ttl.map(|_| cache::cache_key(&request, &state));
 
-            let header_name = HeaderName::from_str(key).map_err(|e| {
-                AlixtError::Config(format!("Invalid Header Name '{}', {:#?}", key, e))
-            })?;
-            let header_value = HeaderValue::from_str(value.as_str()).map_err(|e| {
-                AlixtError::Config(format!(
-                    "Invalid header value for '{}: {}', {:#?}",
-                    key, value, e
-                ))
-            })?;
+            if let (Some(cache), Some(key)) = (cache.as_deref(), &key)
+                && let Some(values) = cache.get(key)
+                && capture.keys().all(|variable| values.contains_key(variable))
+            {
+                for (variable, value) in values {
+                    state.run_variables.insert(variable.clone(), value.clone());
+                    state.export(variable);
+                }
+                run_outcome.outcomes.push(RequestOutcome {
+                    name: request.name,
+                    method: request.method.to_string(),
+                    url: request.url,
+                    passing: AssertionOutcome::Passed,
+                    breaking: false,
+                    status: None,
+                    response_body: None,
+                    duration: Duration::ZERO,
+                    generated_values: Vec::new(),
+                    iterations: Vec::new(),
+                    cached: true,
+                    redirects: Vec::new(),
+                    final_url: None,
+                    remote_addr: None,
+                    tls: None,
+                    events: None,
+                });
+                continue;
+            }
 
-            final_headers.insert(header_name, header_value);
+            let mut outcome = execute_planned_request(clients, request, &mut state).await?;
+            let mut values = HashMap::new();
+            for (variable, pattern) in capture {
+                if let Some(value) = state.run_variables.get(&variable) {
+                    values.insert(variable.clone(), value.clone());
+                    // later capture requests can already use `global.variable`
+                    state.export(&variable);
+                } else {
+                    outcome.passing.push(FailureType::CaptureMissing { variable, pattern });
+                }
+            }
+            if let (Some(cache), Some(key), Some(ttl)) = (cache.as_deref_mut(), key, cache_ttl)
+                && outcome.passing.is_passing()
+            {
+                cache.insert(key, values, ttl);
+            }
+
+            let stop = outcome.passing.is_failed() && outcome.breaking;
+            run_outcome.outcomes.push(outcome);
+            if stop {
+                break;
+            }
         }
+        Ok::<(), AlixtError>(())
     }
+    .await;
 
-    let mut builder = client
-        .request(request.method.clone(), url.clone())
-        .headers(final_headers);
+    let captured = std::mem::take(&mut state.exported);
+    drop(state);
+    result?;
+    let Ok(mut global) = Arc::try_unwrap(global) else {
+        return Err(AlixtError::InternalError(
+            "Global is still shared after the capture requests".to_string(),
+        ));
+    };
+    global.variables.extend(captured);
+    Ok((global, run_outcome))
+}
 
-    if let Some(text) = &request.body {
-        let body = global.substitute_values_in_text(text.as_str());
-        builder = builder.body(body);
-    }
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
 
-    let response = builder.send().await?;
+    use serde_json::json;
 
-    let Some(capture) = request.capture.as_ref() else {
-        return Ok(());
+    use super::*;
+    use crate::models::{context::Global, error::AlixtError};
+
+    use crate::execute::{
+        client,
+        test_server::{Reply, TestCertificate, TestServer, run_config},
     };
 
-    // if there is no capture, it doesnt matter what the response body is, but if there is a
-    // capture block, the response MUST be valid JSON by this point in order to extract values
-    let response = response.json::<Value>().await?;
+    fn failures(request: &serde_json::Value) -> Vec<String> {
+        request["passing"]["Failed"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|failure| failure.as_object()?.keys().next().cloned())
+            .collect()
+    }
 
-    for (variable, pattern) in capture {
-        let value = if pattern.starts_with('/') {
-            response.pointer(pattern)
-        } else {
-            response.get(pattern)
+    #[tokio::test]
+    async fn test_foreach() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/items" => Reply::json(
+                200,
+                json!({
+                    "items": [
+                        { "id": 1, "owner": { "name": "ada" }, "tags": ["a", "b"] },
+                        { "id": 2, "owner": { "name": "bob" }, "tags": ["c"] },
+                    ],
+                    "ids": [10, 20, 30],
+                }),
+            ),
+            "/ids/20" => Reply::status(500),
+            _ => Reply::json(200, json!({})),
+        })
+        .await;
+        let config = r#"
+        [[run]]
+        name = "Foreach"
+        method = "Get"
+        scheme = "Http"
+        host = "{{addr}}"
+        [[run.request]]
+        name = "list"
+        path = "/items"
+        capture = { ids = "/ids", owner = "/items/0/owner" }
+        [[run.request]]
+        name = "from pointer"
+        path = "/items/{{item.id}}/{{item.owner.name}}/{{item.tags.0}}/{{index}}"
+        foreach = "/items"
+        [[run.request]]
+        name = "from variable"
+        path = "/ids/{{item}}"
+        foreach = "ids"
+        assert = { breaking = false, status = 200 }
+        [[run.request]]
+        name = "not an array"
+        path = "/never"
+        foreach = "owner"
+        [[run.request]]
+        name = "breaking"
+        path = "/ids/{{item}}"
+        foreach = "ids"
+        assert = { breaking = true, status = 200 }
+        [[run.request]]
+        name = "after the break"
+        path = "/never"
+        "#;
+        let report = run_config(&server, config, &[]).await.unwrap();
+        let requests = report["runs"][0]["requests"].as_array().unwrap();
+        let paths: Vec<String> = server.requests().into_iter().map(|request| request.path).collect();
+        assert_eq!(
+            paths,
+            [
+                "/items", "/items/1/ada/a/0", "/items/2/bob/c/1", "/ids/10", "/ids/20", "/ids/30",
+                "/ids/10", "/ids/20",
+            ]
+        );
+
+        assert_eq!(requests[1]["passing"], "Passed");
+        assert_eq!(requests[1]["iterations"].as_array().unwrap().len(), 2);
+
+        assert_eq!(failures(&requests[2]), ["IterationsFailed"]);
+        assert_eq!(requests[2]["passing"]["Failed"][0]["IterationsFailed"], json!({ "failed": 1, "total": 3 }));
+        assert_eq!(requests[2]["iterations"].as_array().unwrap().len(), 3);
+
+        assert_eq!(failures(&requests[3]), ["ForeachNotArray"]);
+        assert_eq!(requests[3]["passing"]["Failed"][0]["ForeachNotArray"]["found"], r#"{"name":"ada"}"#);
+
+        // the breaking iteration stops both the foreach and the run
+        assert_eq!(requests[4]["iterations"].as_array().unwrap().len(), 2);
+        assert_eq!(requests.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_setup_captures_become_global() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/login" => Reply::json(200, json!({ "token": "abc" })),
+            _ => Reply::status(200),
+        })
+        .await;
+        let config = r#"
+        [[setup]]
+        name = "Login"
+        method = "Get"
+        scheme = "Http"
+        host = "{{addr}}"
+        vars = { helper = "setup-only" }
+        [[setup.request]]
+        name = "login"
+        path = "/login"
+        capture = { token = "token" }
+
+        [[run]]
+        name = "Uses the token"
+        method = "Get"
+        scheme = "Http"
+        host = "{{addr}}"
+        [[run.request]]
+        name = "use"
+        path = "/use/{{global.token}}"
+        skip_if = "'{{global.helper}}' == 'setup-only'"
+        "#;
+
+        let report = run_config(&server, config, &[]).await.unwrap();
+        // the setup run's own vars are not promoted, only what it captured
+        assert_eq!(report["runs"][0]["requests"][0]["passing"], "Passed");
+        let paths: Vec<_> = server.requests().into_iter().map(|request| request.path).collect();
+        assert_eq!(paths, ["/login", "/use/abc"]);
+    }
+
+    #[tokio::test]
+    async fn test_parallel_error_waits_for_teardown() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/slow" => Reply::status(200).delay(Duration::from_millis(300)),
+            _ => Reply::status(200),
+        })
+        .await;
+        // nothing listens on port 9, so the first run fails with an error right away
+        let config = r#"
+        [[run]]
+        name = "Fails"
+        method = "Get"
+        scheme = "Http"
+        host = "127.0.0.1"
+        port = 9
+        [[run.request]]
+        name = "refused"
+        [[run]]
+        name = "Slow"
+        method = "Get"
+        scheme = "Http"
+        host = "{{addr}}"
+        [[run.request]]
+        name = "slow"
+        path = "/slow"
+        [[run.teardown]]
+        name = "cleanup"
+        path = "/cleanup"
+        "#;
+        let result = run_config(&server, config, &["--jobs", "2"]).await;
+        assert!(matches!(result, Err(AlixtError::Request(_))));
+        let paths: Vec<String> = server.requests().into_iter().map(|request| request.path).collect();
+        assert_eq!(paths, ["/slow", "/cleanup"]);
+    }
+
+    #[tokio::test]
+    async fn test_capture_requests() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/token" => Reply::json(200, json!({ "token": "t-1" })),
+            "/fails" => Reply::status(500),
+            _ => Reply::status(200),
+        })
+        .await;
+        let capture = r#"
+        [[capture.request]]
+        name = "token"
+        method = "Post"
+        scheme = "Http"
+        host = "{{addr}}"
+        path = "/token"
+        capture = { token = "/token" }
+        assert = { breaking = false, status = 200, subset_matches = { "/token" = "t-1" } }
+        "#;
+        let run = r#"
+        [[run]]
+        name = "Uses the token"
+        method = "Get"
+        scheme = "Http"
+        host = "{{addr}}"
+        [[run.request]]
+        name = "me"
+        path = "/me/{{token}}/{{global.token}}"
+        "#;
+        let report = run_config(&server, &format!("{capture}{run}"), &[]).await.unwrap();
+        assert_eq!(report["capture"]["name"], "capture");
+        assert_eq!(report["capture"]["requests"][0]["passing"], "Passed");
+        assert_eq!(report["runs"][0]["requests"][0]["url"], format!("http://{}/me/t-1/t-1", server.addr));
+
+        let failing = r#"
+        [[capture.request]]
+        name = "missing and wrong status"
+        method = "Get"
+        scheme = "Http"
+        host = "{{addr}}"
+        path = "/token"
+        capture = { token = "/token", user = "/user" }
+        assert = { breaking = false, status = 201 }
+        [[capture.request]]
+        name = "breaks"
+        method = "Get"
+        scheme = "Http"
+        host = "{{addr}}"
+        path = "/fails"
+        assert = { breaking = true, status = 200 }
+        [[capture.request]]
+        name = "never sent"
+        method = "Get"
+        scheme = "Http"
+        host = "{{addr}}"
+        path = "/never"
+        "#;
+        let report = run_config(&server, &format!("{failing}{run}"), &[]).await.unwrap();
+        let requests = report["capture"]["requests"].as_array().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(failures(&requests[0]), ["StatusMismatch", "CaptureMissing"]);
+        assert_eq!(requests[0]["passing"]["Failed"][1]["CaptureMissing"]["variable"], "user");
+        assert_eq!(failures(&requests[1]), ["StatusMismatch"]);
+        assert_eq!(report["runs"][0]["skipped"], "a capture request failed");
+        assert_eq!(report["runs"][0]["requests"], json!([]));
+        let paths: Vec<String> = server.requests().into_iter().map(|request| request.path).collect();
+        assert_eq!(paths, ["/token", "/me/t-1/t-1", "/token", "/fails"]);
+    }
+
+    #[tokio::test]
+    async fn test_redirects() {
+        let other = TestServer::start(|_| Reply::status(200)).await;
+        let other_url = format!("http://{}/landing", other.addr);
+        let server = TestServer::start(move |request| {
+            let redirect = |status, location: &str| Reply::status(status).header("Location", location);
+            match request.path.as_str() {
+                "/post-302" | "/put-302" => redirect(302, "/landing"),
+                "/see-other" => redirect(303, "/landing"),
+                "/temporary" => redirect(307, "/landing"),
+                "/cross" => redirect(301, &other_url),
+                "/loop" => redirect(302, "/loop"),
+                _ => Reply::status(200),
+            }
+        })
+        .await;
+        let client = Client::builder()
+            .no_proxy()
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .unwrap();
+        let mut state = RunState::new(Arc::new(Global::seeded(0)));
+        let base = format!("http://{}", server.addr);
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_static("Bearer secret"));
+        headers.insert(PROXY_AUTHORIZATION, HeaderValue::from_static("Basic cHJveHk="));
+        headers.insert(COOKIE, HeaderValue::from_static("session=1"));
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        headers.insert("x-trace", HeaderValue::from_static("kept"));
+        let body = Some(b"{}".to_vec());
+
+        let mut send = async |method: Method, path: &str, limit: usize| {
+            let url = format!("{base}{path}");
+            let (response, hops) = send_following_redirects(
+                &client, method, &url, headers.clone(), body.clone(), limit, &mut state,
+            )
+            .await
+            .unwrap();
+            (response.status().as_u16(), hops)
         };
 
-        // unlike with regular requests, capture variables must be resolved in the response body
-        let Some(value) = value else {
-            return Err(AlixtError::Config(format!(
-                "pattern '{}' not found in response body for request '{}'",
-                pattern, request.name
-            )));
+        for (method, path, expected) in [
+            (Method::POST, "/post-302", Method::GET),
+            (Method::PUT, "/put-302", Method::PUT),
+            (Method::PUT, "/see-other", Method::GET),
+            (Method::POST, "/temporary", Method::POST),
+        ] {
+            let (status, hops) = send(method, path, 10).await;
+            assert_eq!(status, 200);
+            assert_eq!(hops.len(), 1);
+            let landing = server.requests().pop().unwrap();
+            assert_eq!(landing.path, "/landing");
+            assert_eq!(landing.method, expected.as_str(), "after {path}");
+            let keeps_body = expected != Method::GET;
+            assert_eq!(landing.body_text() == "{}", keeps_body, "body after {path}");
+            assert_eq!(landing.header("content-type").is_some(), keeps_body, "content type after {path}");
+            // same origin, the credentials stay
+            assert_eq!(landing.header("authorization"), Some("Bearer secret"));
+        }
+
+        let (status, hops) = send(Method::GET, "/cross", 10).await;
+        assert_eq!(status, 200);
+        assert_eq!(hops[0].status, 301);
+        assert_eq!(hops[0].url, format!("{base}/cross"));
+        assert_eq!(hops[0].location, format!("http://{}/landing", other.addr));
+        let landing = other.requests().pop().unwrap();
+        assert_eq!(landing.header("authorization"), None);
+        assert_eq!(landing.header("proxy-authorization"), None);
+        assert_eq!(landing.header("cookie"), None);
+        assert_eq!(landing.header("x-trace"), Some("kept"));
+
+        let (status, hops) = send(Method::GET, "/loop", 3).await;
+        assert_eq!(status, 302);
+        assert_eq!(hops.len(), 3);
+        assert!(hops.iter().all(|hop| hop.location == format!("{base}/loop")));
+        let (status, hops) = send(Method::GET, "/loop", 0).await;
+        assert_eq!((status, hops.len()), (302, 0));
+    }
+
+    #[tokio::test]
+    async fn test_untrusted_certificate() {
+        let certificate = TestCertificate::localhost();
+        let server = TestServer::start_tls(&certificate, |_| Reply::status(200)).await;
+        let config = r#"
+        [[run]]
+        name = "Private CA"
+        method = "Get"
+        scheme = "Https"
+        host = "{{addr}}"
+        [[run.request]]
+        name = "request"
+        "#;
+
+        let Err(AlixtError::Request(e)) = run_config(&server, config, &[]).await else {
+            panic!("a self signed certificate is not trusted");
         };
+        assert!(client::is_untrusted_certificate(&e));
 
-        let value_string = match value {
-            Value::String(s) => s.clone(),
-            v => v.to_string(),
+        let dir = std::env::temp_dir().join(format!("alixt-ca-cert-{}", std::process::id()));
+        let (cert, _) = certificate.write(&dir);
+        let report = run_config(&server, config, &["--ca-cert", cert.to_str().unwrap()])
+            .await
+            .unwrap();
+        assert_eq!(report["runs"][0]["requests"][0]["passing"], "Passed");
+    }
+
+    #[tokio::test]
+    async fn test_tls_summary() {
+        let certificate = TestCertificate::localhost();
+        let server = TestServer::start_tls(&certificate, |_| Reply::status(200)).await;
+        let dir = std::env::temp_dir().join(format!("alixt-tls-summary-{}", std::process::id()));
+        let (cert, _) = certificate.write(&dir);
+        let config = r#"
+        [[run]]
+        name = "Certificates"
+        method = "Get"
+        scheme = "Https"
+        host = "{{addr}}"
+        [[run.request]]
+        name = "recorded"
+        [[run.request]]
+        name = "asserted"
+        [run.request.assert.tls]
+        cert_expires_in_days_min = 1
+        subject_alt_names_include = ["127.0.0.1"]
+        min_tls_version = "1.3"
+        [[run.request]]
+        name = "mismatch"
+        [run.request.assert.tls]
+        issuer_matches = "^CN=Other"
+        "#;
+
+        let report = run_config(&server, config, &["--ca-cert", cert.to_str().unwrap()])
+            .await
+            .unwrap();
+        let requests = &report["runs"][0]["requests"];
+        // the leaf comes from the request's own connection, the protocol needs an assertion
+        let recorded = &requests[0]["tls"];
+        assert_eq!(recorded["chain"][0]["subject_alt_names"], json!(["localhost", "127.0.0.1"]));
+        assert!(recorded.get("protocol").is_none());
+        assert_eq!(requests[1]["passing"], "Passed");
+        assert_eq!(requests[1]["tls"]["protocol"], "TLSv1.3");
+        assert_eq!(failures(&requests[2]), ["TlsMismatch"]);
+    }
+
+    #[tokio::test]
+    async fn test_sse_timeouts() {
+        let server = TestServer::start(|request| match request.path.as_str() {
+            "/silent" => Reply::silent(),
+            _ => Reply::status(200)
+                .header("Content-Type", "text/event-stream")
+                .body("data: {\"n\": 1}\n\n")
+                .delay(Duration::from_millis(400))
+                .keep_open(),
+        })
+        .await;
+        let config = r#"
+        [[run]]
+        name = "Streams"
+        method = "Get"
+        scheme = "Http"
+        host = "{{addr}}"
+        [[run.request]]
+        name = "silent"
+        path = "/silent"
+        stream = "sse"
+        sse = { timeout = "300ms" }
+        [[run.request]]
+        name = "late"
+        path = "/late"
+        stream = "sse"
+        sse = { timeout = "600ms", until_data = "\"n\": 2" }
+        "#;
+
+        // the timeouts cover waiting for the headers, 300ms and 600ms instead of 300ms and
+        // 400ms + 600ms
+        let start = std::time::Instant::now();
+        let report = run_config(&server, config, &[]).await.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(1150), "{:?}", start.elapsed());
+        let requests = &report["runs"][0]["requests"];
+        assert_eq!(requests[0]["passing"]["Failed"][0]["SseMismatch"]["check"], "timeout");
+        assert_eq!(requests[1]["events"].as_array().map(Vec::len), Some(1));
+        assert_eq!(requests[1]["passing"]["Failed"][0]["SseMismatch"]["check"], "until");
+
+        let Err(AlixtError::Config(e)) =
+            run_config(&server, &config.replace(r#"\"n\": 2"#, "(unclosed"), &[]).await
+        else {
+            panic!("an invalid until_data is a config error");
         };
-        global.variables.insert(variable.to_string(), value_string);
+        assert!(e.starts_with("Invalid sse until_data '(unclosed' for request 'late'"), "{e}");
     }
-    Ok(())
 }
@@ -15,4 +15,9 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 
+pub mod client;
 pub mod http;
+pub mod sse;
+pub mod tls;
+#[cfg(test)]
+pub mod test_server;
@@ -0,0 +1,280 @@
+// This file is part of alixt.
+// Copyright (C) 2025 Devon Harley Offutt
+//
+// alixt is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Reads `stream = "sse"` responses.
+//!
+//! An event stream never finishes on its own, so instead of reading the whole body the response is
+//! read chunk by chunk until the request's limits are reached. Captures and assertions then see an
+//! array with the data of every event, parsed as JSON where it is JSON, so `/0/id` points into the
+//! first event.
+
+use reqwest::Response;
+use serde_json::Value;
+use tokio::time::{Instant, timeout_at};
+
+use crate::models::{
+    config::Assert,
+    error::AlixtError,
+    plan::SseStream,
+    test_data::{AssertionOutcome, FailureType, SseEvent},
+};
+
+/// Why reading the stream stopped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StreamEnd {
+    Closed,
+    MaxEvents,
+    Until,
+    Timeout,
+}
+
+pub struct EventStream {
+    pub events: Vec<SseEvent>,
+    // everything that was received, for the response body of the outcome
+    pub text: String,
+    pub end: StreamEnd,
+}
+
+impl EventStream {
+    /// The data of every event, what captures and assertions are checked against
+    pub fn data(&self) -> Value {
+        self.events
+            .iter()
+            .map(|event| {
+                serde_json::from_str(&event.data).unwrap_or_else(|_| Value::String(event.data.clone()))
+            })
+            .collect()
+    }
+}
+
+/// Reads events until the stream's limits are reached or `deadline` passes, it is set when the
+/// request was sent
+pub async fn read_events(
+    mut response: Response,
+    stream: &SseStream,
+    deadline: Instant,
+) -> Result<EventStream, AlixtError> {
+    let until = stream.until_event.is_some() || stream.until_data.is_some();
+
+    let mut parser = Parser::default();
+    let mut received = Vec::new();
+    let mut events = Vec::new();
+    let end = loop {
+        let chunk = match timeout_at(deadline, response.chunk()).await {
+            Ok(chunk) => chunk?,
+            Err(_) => break StreamEnd::Timeout,
+        };
+        let Some(chunk) = chunk else {
+            break StreamEnd::Closed;
+        };
+        received.extend_from_slice(&chunk);
+        let mut end = None;
+        for event in parser.feed(&chunk) {
+            let matches = until
+                && stream.until_event.as_ref().is_none_or(|name| *name == event.event)
+                && stream.until_data.as_ref().is_none_or(|regex| regex.is_match(&event.data));
+            events.push(event);
+            if matches {
+                end = Some(StreamEnd::Until);
+            } else if stream.max_events.is_some_and(|max| events.len() >= max) {
+                end = Some(StreamEnd::MaxEvents);
+            }
+            if end.is_some() {
+                break;
+            }
+        }
+        if let Some(end) = end {
+            break end;
+        }
+    };
+
+    Ok(EventStream {
+        events,
+        text: String::from_utf8_lossy(&received).into_owned(),
+        end,
+    })
+}
+
+pub fn check_sse(
+    stream: &EventStream,
+    options: &SseStream,
+    assert: Option<&Assert>,
+    outcome: &mut AssertionOutcome,
+) {
+    let mut mismatch = |check: &str, expected: String, found: String| {
+        outcome.push(FailureType::SseMismatch {
+            check: check.to_string(),
+            expected,
+            found,
+        });
+    };
+
+    if (options.until_event.is_some() || options.until_data.is_some())
+        && stream.end != StreamEnd::Until
+    {
+        let expected = options
+            .until_event
+            .iter()
+            .map(|name| format!("event '{name}'"))
+            .chain(options.until_data.iter().map(|regex| format!("data matching '{regex}'")))
+            .collect::<Vec<_>>()
+            .join(" with ");
+        let found = match stream.end {
+            StreamEnd::Closed => "the stream closed",
+            StreamEnd::MaxEvents => "max_events was reached",
+            StreamEnd::Timeout | StreamEnd::Until => "timed out",
+        };
+        mismatch(
+            "until",
+            expected,
+            format!("{found} after {} events", stream.events.len()),
+        );
+    }
+
+    let Some(assert) = assert else {
+        return;
+    };
+    if let Some(expected) = assert.sse_event_count
+        && stream.events.len() != expected
+    {
+        mismatch(
+            "sse_event_count",
+            expected.to_string(),
+            stream.events.len().to_string(),
+        );
+    }
+    if let Some(expected) = &assert.sse_events {
+        let mut names = stream.events.iter().map(|event| &event.event);
+        if !expected.iter().all(|name| names.any(|found| found == name)) {
+            let found: Vec<&str> = stream.events.iter().map(|event| event.event.as_str()).collect();
+            mismatch("sse_events", expected.join(", "), found.join(", "));
+        }
+    }
+}
+
+/// Splits the stream into events as the SSE spec describes, lines may be split across chunks
+#[derive(Default)]
+struct Parser {
+    line: Vec<u8>,
+    event: Option<String>,
+    data: Vec<String>,
+    // the last event ID, it stays until another `id:` field replaces it
+    id: Option<String>,
+}
+
+impl Parser {
+    fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+        for &byte in chunk {
+            if byte != b'\n' {
+                self.line.push(byte);
+                continue;
+            }
+            let mut line = std::mem::take(&mut self.line);
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            let line = String::from_utf8_lossy(&line);
+            if line.is_empty() {
+                // an event without data is dropped, its name does not carry over
+                let event = self.event.take();
+                if !self.data.is_empty() {
+                    events.push(SseEvent {
+                        event: event.unwrap_or_else(|| "message".to_string()),
+                        data: std::mem::take(&mut self.data).join("\n"),
+                        id: self.id.clone(),
+                    });
+                }
+                continue;
+            }
+            // lines starting with a colon are comments, often sent to keep the connection open
+            if line.starts_with(':') {
+                continue;
+            }
+            let (field, value) = match line.split_once(':') {
+                Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+                None => (line.as_ref(), ""),
+            };
+            match field {
+                "event" => self.event = Some(value.to_string()),
+                "data" => self.data.push(value.to_string()),
+                "id" => self.id = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sse_events() {
+        let mut parser = Parser::default();
+        let mut events = parser.feed(b": keep-alive\r\n\r\nevent: price\r\ndata: {\"sym");
+        assert!(events.is_empty());
+        events.extend(parser.feed(b"bol\": \"ACME\"}\r\nid: 7\r\n\r\ndata: line one\ndata:line two\n\nevent: done\n\n"));
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, "price");
+        assert_eq!(events[0].id.as_deref(), Some("7"));
+        assert_eq!(events[1].event, "message");
+        assert_eq!(events[1].data, "line one\nline two");
+        // the last event ID carries over to the events without an `id:` line
+        assert_eq!(events[1].id.as_deref(), Some("7"));
+        let mut later = Parser::default();
+        let ids: Vec<_> = later
+            .feed(b"id: 1\ndata: a\n\ndata: b\n\nid: 2\ndata: c\n\nid\ndata: d\n\n")
+            .into_iter()
+            .map(|event| event.id)
+            .collect();
+        assert_eq!(ids, [Some("1".to_string()), Some("1".to_string()), Some("2".to_string()), Some(String::new())]);
+
+        let stream = EventStream {
+            events,
+            text: String::new(),
+            end: StreamEnd::Closed,
+        };
+        assert_eq!(stream.data(), json!([{ "symbol": "ACME" }, "line one\nline two"]));
+
+        let options = SseStream {
+            max_events: None,
+            until_event: Some("done".to_string()),
+            until_data: None,
+            timeout: std::time::Duration::from_secs(1),
+        };
+        let assert: Assert = toml::from_str(
+            "breaking = false\nsse_event_count = 2\nsse_events = [\"price\", \"message\"]",
+        )
+        .unwrap();
+        let mut outcome = AssertionOutcome::Passed;
+        check_sse(&stream, &options, Some(&assert), &mut outcome);
+        // the stream closed before the event named done
+        let AssertionOutcome::Failed(failures) = outcome else {
+            panic!("expected the until check to fail");
+        };
+        assert_eq!(failures.len(), 1);
+
+        let assert: Assert =
+            toml::from_str("breaking = false\nsse_events = [\"message\", \"price\"]").unwrap();
+        let mut outcome = AssertionOutcome::Passed;
+        check_sse(&stream, &SseStream { until_event: None, ..options }, Some(&assert), &mut outcome);
+        assert!(!outcome.is_passing());
+    }
+}
@@ -0,0 +1,327 @@
+// This file is part of alixt.
+// Copyright (C) 2025 Devon Harley Offutt
+//
+// alixt is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! A small HTTP/1.1 server for the tests that need to send real requests, and a helper that runs
+//! a config against it and returns the JSON report.
+
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use clap::Parser;
+use serde_json::Value;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use tokio_rustls::{
+    TlsAcceptor,
+    rustls::{
+        ServerConfig,
+        crypto::ring,
+        pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    },
+};
+
+use crate::models::{cli::Args, error::AlixtError};
+
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    pub method: String,
+    pub path: String,
+    // names are lower case
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl RecordedRequest {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+    pub fn body_text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+pub struct Reply {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    // send nothing at all and keep the connection open
+    silent: bool,
+    // keep the connection open after the body, like an event stream that never ends
+    keep_open: bool,
+    delay: Duration,
+}
+
+impl Reply {
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+            silent: false,
+            keep_open: false,
+            delay: Duration::ZERO,
+        }
+    }
+    pub fn json(status: u16, body: Value) -> Self {
+        Self::status(status)
+            .header("Content-Type", "application/json")
+            .body(body.to_string())
+    }
+    pub fn silent() -> Self {
+        Self {
+            silent: true,
+            ..Self::status(200)
+        }
+    }
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+    pub fn keep_open(mut self) -> Self {
+        self.keep_open = true;
+        self
+    }
+}
+
+pub struct TestServer {
+    pub addr: SocketAddr,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+/// A self signed certificate for `localhost` and `127.0.0.1`, in PEM
+pub struct TestCertificate {
+    pub cert_pem: String,
+    pub key_pem: String,
+}
+
+impl TestCertificate {
+    pub fn localhost() -> Self {
+        let names = vec!["localhost".to_string(), "127.0.0.1".to_string()];
+        let certified = rcgen::generate_simple_self_signed(names).expect("generate certificate");
+        Self {
+            cert_pem: certified.cert.pem(),
+            key_pem: certified.signing_key.serialize_pem(),
+        }
+    }
+
+    /// Writes both files into `dir`, returning the certificate and key paths
+    pub fn write(&self, dir: &Path) -> (PathBuf, PathBuf) {
+        std::fs::create_dir_all(dir).expect("create certificate dir");
+        let cert = dir.join("cert.pem");
+        let key = dir.join("key.pem");
+        std::fs::write(&cert, &self.cert_pem).expect("write certificate");
+        std::fs::write(&key, &self.key_pem).expect("write key");
+        (cert, key)
+    }
+}
+
+impl TestServer {
+    /// Answers every request with `handler`, one connection per request
+    pub async fn start(handler: impl Fn(&RecordedRequest) -> Reply + Send + Sync + 'static) -> Self {
+        Self::listen(None, handler).await
+    }
+
+    /// Same as `start`, behind TLS with `certificate`
+    pub async fn start_tls(
+        certificate: &TestCertificate,
+        handler: impl Fn(&RecordedRequest) -> Reply + Send + Sync + 'static,
+    ) -> Self {
+        let certs = CertificateDer::pem_slice_iter(certificate.cert_pem.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .expect("parse certificate");
+        let key = PrivateKeyDer::from_pem_slice(certificate.key_pem.as_bytes()).expect("parse key");
+        let config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()
+            .expect("protocol versions")
+            .with_no_client_auth()
+            .with_single_cert(certs, key)
+            .expect("server certificate");
+        Self::listen(Some(TlsAcceptor::from(Arc::new(config))), handler).await
+    }
+
+    /// A proxy that only opens CONNECT tunnels, its requests are the CONNECTs it was sent
+    pub async fn start_proxy() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind test proxy");
+        let addr = listener.local_addr().expect("test proxy address");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let recorded = recorded.clone();
+                tokio::spawn(async move {
+                    let mut socket: Box<dyn Stream> = Box::new(socket);
+                    let Some(request) = read_request(&mut socket).await else {
+                        return;
+                    };
+                    let target = TcpStream::connect(&request.path).await;
+                    recorded.lock().unwrap().push(request);
+                    let Ok(mut target) = target else {
+                        let _ = socket.write_all(b"HTTP/1.1 502 Bad Gateway\r\n\r\n").await;
+                        return;
+                    };
+                    if socket.write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n").await.is_ok() {
+                        let _ = tokio::io::copy_bidirectional(&mut socket, &mut target).await;
+                    }
+                });
+            }
+        });
+        Self { addr, requests }
+    }
+
+    async fn listen(
+        tls: Option<TlsAcceptor>,
+        handler: impl Fn(&RecordedRequest) -> Reply + Send + Sync + 'static,
+    ) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.expect("bind test server");
+        let addr = listener.local_addr().expect("test server address");
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let handler = Arc::new(handler);
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let handler = handler.clone();
+                let recorded = recorded.clone();
+                let tls = tls.clone();
+                tokio::spawn(async move {
+                    let mut socket: Box<dyn Stream> = match tls {
+                        Some(tls) => match tls.accept(socket).await {
+                            Ok(stream) => Box::new(stream),
+                            // the client gave up on the handshake, e.g. an untrusted certificate
+                            Err(_) => return,
+                        },
+                        None => Box::new(socket),
+                    };
+                    let Some(request) = read_request(&mut socket).await else {
+                        return;
+                    };
+                    let reply = handler(&request);
+                    recorded.lock().unwrap().push(request);
+                    tokio::time::sleep(reply.delay).await;
+                    if !reply.silent {
+                        let _ = socket.write_all(&encode(&reply)).await;
+                    }
+                    if reply.silent || reply.keep_open {
+                        std::future::pending::<()>().await;
+                    }
+                });
+            }
+        });
+        Self { addr, requests }
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+async fn read_request(socket: &mut Box<dyn Stream>) -> Option<RecordedRequest> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end;
+        }
+        let read = socket.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+    let head = String::from_utf8_lossy(&buffer[..head_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split(' ');
+    let method = request_line.next()?.to_string();
+    let path = request_line.next()?.to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+        .collect();
+    let length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = buffer[head_end + 4..].to_vec();
+    while body.len() < length {
+        let read = socket.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+    Some(RecordedRequest {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+fn encode(reply: &Reply) -> Vec<u8> {
+    let mut head = format!("HTTP/1.1 {} Test\r\n", reply.status);
+    for (name, value) in &reply.headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    if !reply.keep_open {
+        head.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n", reply.body.len()));
+    }
+    head.push_str("\r\n");
+    let mut bytes = head.into_bytes();
+    bytes.extend_from_slice(&reply.body);
+    bytes
+}
+
+/// Runs `config` from a fresh directory and returns the JSON report, `{{addr}}` in the config is
+/// replaced with the server's address first
+pub async fn run_config(server: &TestServer, config: &str, extra_args: &[&str]) -> Result<Value, AlixtError> {
+    let dir = std::env::temp_dir().join(format!(
+        "alixt-test-{}-{}",
+        std::process::id(),
+        server.addr.port()
+    ));
+    std::fs::create_dir_all(&dir)?;
+    let file = dir.join("tests.toml");
+    std::fs::write(&file, config.replace("{{addr}}", &server.addr.to_string()))?;
+
+    // proxies from the environment would otherwise see the requests to the test server
+    let mut args = vec!["alixt", "-f", file.to_str().unwrap(), "-m", "json", "--no-proxy", "*"];
+    args.extend_from_slice(extra_args);
+    let mut output = Vec::new();
+    crate::run(&mut output, Args::parse_from(args)).await?;
+    Ok(serde_json::from_slice(&output)?)
+}
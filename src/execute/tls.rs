@@ -0,0 +1,481 @@
+// This file is part of alixt.
+// Copyright (C) 2025 Devon Harley Offutt
+//
+// alixt is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Inspects the TLS connection a request was made over.
+//!
+//! The leaf certificate is read from the request's own connection. reqwest does not expose the
+//! rest of the handshake, so a `min_tls_version` assertion gets a second, separate handshake with
+//! the final URL's host, through the same proxy the request used. That handshake accepts any
+//! certificate, it only reports what the server negotiated; whether the chain is trusted is still
+//! decided by the request itself.
+
+use std::{
+    net::IpAddr,
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use regex::Regex;
+use reqwest::Url;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use percent_encoding::percent_decode_str;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::timeout,
+};
+use tokio_rustls::{
+    TlsConnector,
+    rustls::{
+        self, ClientConfig, ClientConnection, DigitallySignedStruct, ProtocolVersion, SignatureScheme,
+        client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+        crypto::CryptoProvider,
+        pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime, pem::PemObject},
+    },
+};
+use x509_parser::{extensions::GeneralName, prelude::*};
+
+use crate::execute::client::ProxySettings;
+use crate::models::{
+    config::{TlsAssert, TlsVersion},
+    plan::{ClientSettings, TlsFiles},
+    test_data::{AssertionOutcome, CertificateSummary, FailureType, TlsSummary},
+};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+// a proxy's reply to CONNECT is a status line and a few headers
+const MAX_CONNECT_REPLY: usize = 8 * 1024;
+
+/// Summarizes the certificate a response's connection was made with, reqwest keeps only the leaf
+pub fn peer_summary(certificate: &[u8]) -> Result<TlsSummary, String> {
+    Ok(TlsSummary {
+        protocol: None,
+        cipher_suite: None,
+        chain: vec![summarize_certificate(&CertificateDer::from(certificate))?],
+    })
+}
+
+/// Connects to the host of `url`, or to `unix_socket`, and summarizes the negotiated protocol and
+/// the certificate chain. The run's client certificate is presented, for servers that require one.
+/// The connection goes through the proxy reqwest would use for `url`, otherwise the run's resolve
+/// overrides pick the address.
+pub async fn inspect(
+    url: &str,
+    settings: &ClientSettings,
+    unix_socket: Option<&Path>,
+    proxy: &ProxySettings,
+) -> Result<TlsSummary, String> {
+    let url = Url::parse(url).map_err(|e| format!("invalid URL '{url}': {e}"))?;
+    if url.scheme() != "https" {
+        return Err(format!("'{url}' is not an https URL"));
+    }
+    let Some(host) = url.host_str() else {
+        return Err(format!("'{url}' has no host"));
+    };
+    // IPv6 hosts keep their brackets in a URL
+    let host = host.trim_start_matches('[').trim_end_matches(']').to_string();
+    let port = url.port_or_known_default().unwrap_or(443);
+    let server_name =
+        ServerName::try_from(host.clone()).map_err(|e| format!("invalid server name '{host}': {e}"))?;
+
+    let connector = TlsConnector::from(Arc::new(client_config(&settings.tls)?));
+    let handshake = async {
+        #[cfg(unix)]
+        if let Some(socket) = unix_socket {
+            let stream = tokio::net::UnixStream::connect(socket).await?;
+            let stream = connector.connect(server_name, stream).await?;
+            return Ok(summarize_connection(stream.get_ref().1));
+        }
+        #[cfg(not(unix))]
+        let _ = unix_socket;
+        let stream = if let Some(proxy_url) = proxy.for_https(&host) {
+            tunnel(proxy_url, proxy.auth.as_ref(), &host, port).await?
+        } else if let Some(ip) = settings.resolve.get(&host.to_ascii_lowercase()) {
+            TcpStream::connect((*ip, port)).await?
+        } else {
+            TcpStream::connect((host.as_str(), port)).await?
+        };
+        let stream = connector.connect(server_name, stream).await?;
+        Ok::<_, std::io::Error>(summarize_connection(stream.get_ref().1))
+    };
+    timeout(HANDSHAKE_TIMEOUT, handshake)
+        .await
+        .map_err(|_| format!("handshake with {host}:{port} timed out"))?
+        .map_err(|e| format!("handshake with {host}:{port} failed: {e}"))?
+}
+
+/// Asks an http proxy for a tunnel to `host:port`, as reqwest does before an https request
+async fn tunnel(
+    proxy: &str,
+    auth: Option<&(String, String)>,
+    host: &str,
+    port: u16,
+) -> std::io::Result<TcpStream> {
+    let error = |message: String| std::io::Error::other(message);
+    // reqwest reads a proxy without a scheme as an http one
+    let proxy = if proxy.contains("://") {
+        Url::parse(proxy)
+    } else {
+        Url::parse(&format!("http://{proxy}"))
+    }
+    .map_err(|e| error(format!("invalid proxy URL '{proxy}': {e}")))?;
+    if proxy.scheme() != "http" {
+        return Err(error(format!(
+            "tls can only be inspected through an http proxy, not {}",
+            proxy.scheme()
+        )));
+    }
+    let Some(proxy_host) = proxy.host_str() else {
+        return Err(error(format!("proxy URL '{proxy}' has no host")));
+    };
+    let proxy_host = proxy_host.trim_start_matches('[').trim_end_matches(']');
+    let mut stream =
+        TcpStream::connect((proxy_host, proxy.port_or_known_default().unwrap_or(80))).await?;
+
+    let target = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(_)) => format!("[{host}]:{port}"),
+        _ => format!("{host}:{port}"),
+    };
+    let mut request = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n");
+    // credentials in the proxy URL are used when none were given separately, as in reqwest
+    let credentials = auth.cloned().or_else(|| {
+        let decode = |value: &str| percent_decode_str(value).decode_utf8_lossy().into_owned();
+        (!proxy.username().is_empty())
+            .then(|| (decode(proxy.username()), decode(proxy.password().unwrap_or_default())))
+    });
+    if let Some((username, password)) = credentials {
+        let encoded = BASE64_STANDARD.encode(format!("{username}:{password}"));
+        request.push_str(&format!("Proxy-Authorization: Basic {encoded}\r\n"));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+
+    // read byte by byte, so nothing of the handshake that follows the reply is consumed
+    let mut reply = Vec::new();
+    while !reply.ends_with(b"\r\n\r\n") {
+        if reply.len() > MAX_CONNECT_REPLY {
+            return Err(error("the proxy's reply to CONNECT is too long".to_string()));
+        }
+        reply.push(stream.read_u8().await?);
+    }
+    let reply = String::from_utf8_lossy(&reply);
+    let status_line = reply.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some("200") => Ok(stream),
+        _ => Err(error(format!("the proxy refused the tunnel: {status_line}"))),
+    }
+}
+
+fn summarize_connection(connection: &ClientConnection) -> Result<TlsSummary, String> {
+    let protocol = match connection.protocol_version() {
+        Some(ProtocolVersion::TLSv1_2) => "TLSv1.2".to_string(),
+        Some(ProtocolVersion::TLSv1_3) => "TLSv1.3".to_string(),
+        Some(other) => format!("{other:?}"),
+        None => "unknown".to_string(),
+    };
+    let cipher_suite = connection
+        .negotiated_cipher_suite()
+        .map(|suite| format!("{:?}", suite.suite()))
+        .unwrap_or_else(|| "unknown".to_string());
+    let chain = connection
+        .peer_certificates()
+        .unwrap_or_default()
+        .iter()
+        .map(summarize_certificate)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(TlsSummary {
+        protocol: Some(protocol),
+        cipher_suite: Some(cipher_suite),
+        chain,
+    })
+}
+
+fn client_config(tls: &TlsFiles) -> Result<ClientConfig, String> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|e| e.to_string())?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)));
+
+    let Some(cert) = &tls.client_cert else {
+        return Ok(builder.with_no_client_auth());
+    };
+    let key = tls.client_key.as_ref().unwrap_or(cert);
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("could not read '{}': {e}", cert.display()))?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| format!("could not read '{}': {e}", key.display()))?;
+    builder
+        .with_client_auth_cert(certs, key)
+        .map_err(|e| e.to_string())
+}
+
+fn summarize_certificate(der: &CertificateDer<'_>) -> Result<CertificateSummary, String> {
+    let (_, cert) = X509Certificate::from_der(der.as_ref())
+        .map_err(|e| format!("could not parse a certificate of the chain: {e}"))?;
+
+    let not_after = cert.validity().not_after.timestamp();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    let subject_alt_names = match cert.subject_alternative_name() {
+        Ok(Some(san)) => san
+            .value
+            .general_names
+            .iter()
+            .filter_map(|name| match name {
+                GeneralName::DNSName(dns) => Some(dns.to_string()),
+                GeneralName::IPAddress(bytes) => <[u8; 4]>::try_from(*bytes)
+                    .map(IpAddr::from)
+                    .or_else(|_| <[u8; 16]>::try_from(*bytes).map(IpAddr::from))
+                    .ok()
+                    .map(|ip| ip.to_string()),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    Ok(CertificateSummary {
+        subject: cert.subject().to_string(),
+        issuer: cert.issuer().to_string(),
+        not_after: humantime::format_rfc3339_seconds(
+            UNIX_EPOCH + Duration::from_secs(not_after.max(0) as u64),
+        )
+        .to_string(),
+        expires_in_days: (not_after - now).div_euclid(86_400),
+        subject_alt_names,
+    })
+}
+
+/// Checks the leaf certificate and the negotiated protocol against the `tls` assertion block
+pub fn check_tls(summary: &TlsSummary, expected: &TlsAssert, outcome: &mut AssertionOutcome) {
+    let mut mismatch = |check: &str, expected: String, found: String| {
+        outcome.push(FailureType::TlsMismatch {
+            check: check.to_string(),
+            expected,
+            found,
+        });
+    };
+
+    if let Some(min) = expected.min_tls_version {
+        let protocol = summary.protocol.as_deref().unwrap_or("unknown");
+        let found = match protocol {
+            "TLSv1.2" => Some(TlsVersion::Tls12),
+            "TLSv1.3" => Some(TlsVersion::Tls13),
+            _ => None,
+        };
+        if found.is_none_or(|found| found < min) {
+            mismatch("min_tls_version", format!(">= {min}"), protocol.to_string());
+        }
+    }
+
+    let Some(leaf) = summary.chain.first() else {
+        if expected.cert_expires_in_days_min.is_some()
+            || expected.issuer_matches.is_some()
+            || expected.subject_alt_names_include.is_some()
+        {
+            mismatch("certificate", "a certificate".to_string(), "<none>".to_string());
+        }
+        return;
+    };
+    if let Some(min) = expected.cert_expires_in_days_min
+        && leaf.expires_in_days < min
+    {
+        mismatch(
+            "cert_expires_in_days_min",
+            format!(">= {min}"),
+            leaf.expires_in_days.to_string(),
+        );
+    }
+    if let Some(pattern) = &expected.issuer_matches {
+        match Regex::new(pattern) {
+            Ok(regex) if regex.is_match(&leaf.issuer) => {}
+            Ok(_) => mismatch("issuer_matches", pattern.clone(), leaf.issuer.clone()),
+            Err(_) => mismatch(
+                "issuer_matches",
+                pattern.clone(),
+                "Invalid Regex Syntax".to_string(),
+            ),
+        }
+    }
+    for name in expected.subject_alt_names_include.iter().flatten() {
+        if !leaf
+            .subject_alt_names
+            .iter()
+            .any(|san| san.eq_ignore_ascii_case(name))
+        {
+            mismatch(
+                "subject_alt_names_include",
+                name.clone(),
+                leaf.subject_alt_names.join(", "),
+            );
+        }
+    }
+}
+
+/// Only used for the inspection handshake, which reports the certificates instead of trusting them
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::execute::test_server::{Reply, TestCertificate, TestServer};
+
+    #[tokio::test]
+    async fn test_inspect() {
+        let server =
+            TestServer::start_tls(&TestCertificate::localhost(), |_| Reply::status(200)).await;
+        let url = format!("https://localhost:{}/", server.addr.port());
+        let settings = ClientSettings::default();
+
+        let summary = inspect(&url, &settings, None, &ProxySettings::default())
+            .await
+            .unwrap();
+        assert_eq!(summary.protocol.as_deref(), Some("TLSv1.3"));
+        assert!(summary.cipher_suite.is_some());
+        let leaf = &summary.chain[0];
+        assert_eq!(leaf.subject_alt_names, ["localhost", "127.0.0.1"]);
+        assert_eq!(leaf.subject, leaf.issuer);
+        assert!(leaf.expires_in_days > 0);
+
+        // with a proxy, the handshake is made through a tunnel to the same host
+        let proxy = TestServer::start_proxy().await;
+        let proxied = ProxySettings {
+            https: Some(format!("http://{}", proxy.addr)),
+            auth: Some(("ci".to_string(), "secret".to_string())),
+            ..Default::default()
+        };
+        let tunneled = inspect(&url, &settings, None, &proxied).await.unwrap();
+        assert_eq!(tunneled.chain[0].subject_alt_names, leaf.subject_alt_names);
+        let connects = proxy.requests();
+        assert_eq!(connects.len(), 1);
+        assert_eq!(connects[0].method, "CONNECT");
+        assert_eq!(connects[0].path, format!("localhost:{}", server.addr.port()));
+        assert_eq!(connects[0].header("proxy-authorization"), Some("Basic Y2k6c2VjcmV0"));
+
+        let bypassed = ProxySettings {
+            no_proxy: Some("localhost".to_string()),
+            ..proxied
+        };
+        inspect(&url, &settings, None, &bypassed).await.unwrap();
+        assert_eq!(proxy.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_tls_assertions() {
+        let summary = TlsSummary {
+            protocol: Some("TLSv1.2".to_string()),
+            cipher_suite: Some("TLS13_AES_128_GCM_SHA256".to_string()),
+            chain: vec![CertificateSummary {
+                subject: "CN=localhost".to_string(),
+                issuer: "CN=Test CA, O=alixt".to_string(),
+                not_after: "2026-11-17T00:00:00Z".to_string(),
+                expires_in_days: 29,
+                subject_alt_names: vec!["localhost".to_string(), "127.0.0.1".to_string()],
+            }],
+        };
+        let passing = TlsAssert {
+            cert_expires_in_days_min: Some(7),
+            issuer_matches: Some("CN=Test CA".to_string()),
+            subject_alt_names_include: Some(vec!["LOCALHOST".to_string(), "127.0.0.1".to_string()]),
+            min_tls_version: Some(TlsVersion::Tls12),
+        };
+        let mut outcome = AssertionOutcome::Passed;
+        check_tls(&summary, &passing, &mut outcome);
+        assert!(outcome.is_passing());
+
+        let failing = TlsAssert {
+            cert_expires_in_days_min: Some(30),
+            issuer_matches: Some("^CN=Other".to_string()),
+            subject_alt_names_include: Some(vec!["example.com".to_string()]),
+            min_tls_version: Some(TlsVersion::Tls13),
+        };
+        let mut outcome = AssertionOutcome::Passed;
+        check_tls(&summary, &failing, &mut outcome);
+        let AssertionOutcome::Failed(failures) = outcome else {
+            panic!("expected failures, got {outcome:?}");
+        };
+        let checks: Vec<_> = failures
+            .iter()
+            .filter_map(|failure| match failure {
+                FailureType::TlsMismatch { check, .. } => Some(check.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            checks,
+            [
+                "min_tls_version",
+                "cert_expires_in_days_min",
+                "issuer_matches",
+                "subject_alt_names_include"
+            ]
+        );
+    }
+}
@@ -21,21 +21,20 @@ pub mod utils;
 
 use std::{path::Path, sync::Arc};
 
-use reqwest::Client;
-
 use crate::{
+    execute::client::{self, ClientOptions, Clients, ProxySettings},
     models::{
         cli::OutputFormat, config::Config, context::Global, error::AlixtError, plan::TestPlan,
     },
     reporting::render::{generate_json, generate_table, generate_text},
-    utils::env,
+    utils::{cache::CaptureCache, env},
 };
 
 pub async fn run<W: std::io::Write>(
     writer: &mut W,
     args: models::cli::Args,
 ) -> Result<(), AlixtError> {
-    let Some(config_file) = args.file else {
+    let Some(config_file) = args.file.clone() else {
         return Err(AlixtError::InternalError(
             "Somehow an arg.file containing None got into run()".to_string(),
         ));
@@ -47,36 +46,77 @@ pub async fn run<W: std::io::Write>(
         .unwrap_or_else(|| Path::new("."))
         .to_owned();
 
-    let plan = TestPlan::from_config(config, &config_dir)?;
+    let mut plan = TestPlan::from_config(config, &config_dir)?;
+    let resolve = args
+        .resolve
+        .iter()
+        .map(|value| client::parse_resolve_arg(value))
+        .collect::<Result<Vec<_>, _>>()?;
+    plan.override_resolve(&resolve);
 
-    #[allow(unused)]
-    let mut global = Global::new();
-    let client = Client::builder()
-        .danger_accept_invalid_certs(args.insecure)
-        .build()?;
+    let mut global = match args.seed {
+        Some(seed) => Global::seeded(seed),
+        None => Global::new(),
+    };
+    if let Some(capture_plan) = &plan.capture
+        && let Some(env_map) = &capture_plan.environment_variables
+    {
+        let dot_env_vars = if let Some(path) = &capture_plan.env_file {
+            Some(env::load_env_file(path)?)
+        } else {
+            None
+        };
 
-    if let Some(capture_plan) = &plan.capture {
-        if let Some(env_map) = &capture_plan.environment_variables {
-            let dot_env_vars = if let Some(path) = &capture_plan.env_file {
-                Some(env::load_env_file(path)?)
-            } else {
-                None
-            };
+        let captured = env::capture_system_environment(env_map, dot_env_vars)?;
 
-            let captured = env::capture_system_environment(env_map, dot_env_vars)?;
+        global.env_variables.extend(captured);
+    }
 
-            global.env_variables.extend(captured);
-        }
+    // static vars are resolved after the environment, so they can be built from env values, and
+    // before the capture requests, so those can use them too
+    if let Some(vars) = &plan.vars {
+        global.add_variables(vars);
+    }
+
+    // built after the variables are known, since proxy credentials can come from them
+    let options = ClientOptions {
+        insecure: args.insecure,
+        proxy: ProxySettings::resolve(&args, plan.proxy.as_ref(), &global)?,
+        ca_cert: args.ca_cert.clone(),
+    };
+    let clients = Clients::build(&plan, &options)?;
 
-        if let Some(requests) = &capture_plan.requests {
-            for request in requests {
-                execute::http::execute_capture_request(&client, request, &mut global).await?;
-            }
+    let mut capture = None;
+    if let Some(steps) = plan.capture.as_mut().and_then(|capture| capture.requests.take()) {
+        // the cache file is only touched when a capture request asks for it
+        let mut cache = if steps.iter().any(|step| step.cache_ttl.is_some()) {
+            let Some(path) = CaptureCache::default_path() else {
+                return Err(AlixtError::Config(
+                    "Capture cache needs $XDG_CACHE_HOME or $HOME to be set".to_string(),
+                ));
+            };
+            Some(CaptureCache::load(path, args.refresh_captures))
+        } else {
+            None
+        };
+        let (captured_global, capture_outcome) =
+            execute::http::execute_capture_run(
+            &clients.select(&plan.client, steps.iter().map(|step| &step.request))?,
+            steps,
+            global,
+            cache.as_mut(),
+        )
+        .await?;
+        global = captured_global;
+        capture = Some(capture_outcome);
+        if let Some(cache) = cache {
+            cache.save()?;
         }
     }
 
     let global = Arc::new(global);
-    let outcome = execute::http::execute_test(&client, plan, global).await?;
+    let outcome =
+        execute::http::execute_test(&clients, plan, global, capture, args.jobs as usize).await?;
 
     match args.mode {
         OutputFormat::Text => {
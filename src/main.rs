@@ -15,7 +15,7 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 
-use alixt::{models::{cli::Args, error::AlixtError}, utils};
+use alixt::{execute::client, models::{cli::Args, error::AlixtError}, utils};
 use clap::Parser;
 use colored::Colorize;
 use std::process::exit;
@@ -58,12 +58,12 @@ async fn main() {
     match alixt::run(&mut writer, args).await {
         Ok(_) => exit(0),
         Err(AlixtError::Request(e)) => {
-            let err_msg = format!("{e:#?}");
             eprintln!("Error: {e:#?}");
 
-            if err_msg.contains("InvalidCertificate") {
+            if client::is_untrusted_certificate(&e) {
                 eprintln!("\n{}The server's certificate is not trusted.", "HINT: ".yellow().bold());
-                eprintln!("    Try running with {} or {} to bypass this check", "--insecure".green(), "-k".green())
+                eprintln!("    If it is signed by a private CA, trust that CA with {} or {} in the config", "--ca-cert <PEM>".green(), "ca_cert".green());
+                eprintln!("    As a last resort, {} / {} disables verification entirely", "--insecure".green(), "-k".green())
             }
             exit(1);
         },
@@ -60,9 +60,41 @@ pub struct Args {
     #[arg(short = 'k', long)]
     pub insecure: bool,
 
+    /// Trust the CA certificates in this PEM file, in addition to the system roots
+    #[arg(long, value_name = "PATH")]
+    pub ca_cert: Option<PathBuf>,
+
     /// See detailed information on assertion failures
     #[arg(short = 'v', long)]
     pub verbose: bool,
+
+    /// Seed the random template functions (uuid(), random_int(), ...) for reproducible runs
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    /// How many runs may execute at the same time, runs only wait for their depends_on
+    #[arg(short = 'j', long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    pub jobs: u64,
+
+    /// Ignore cached capture values and run every capture request again
+    #[arg(long)]
+    pub refresh_captures: bool,
+
+    /// Send every request through this proxy, overrides [proxy] and the environment
+    #[arg(long, value_name = "URL")]
+    pub proxy: Option<String>,
+
+    /// Comma separated hosts that bypass the proxy
+    #[arg(long, value_name = "HOSTS")]
+    pub no_proxy: Option<String>,
+
+    /// Proxy credentials
+    #[arg(long, value_name = "USER:PASSWORD")]
+    pub proxy_user: Option<String>,
+
+    /// Connect to ADDRESS whenever a request goes to HOST, keeping the Host header and SNI (repeatable)
+    #[arg(long, value_name = "HOST:PORT:ADDRESS")]
+    pub resolve: Vec<String>,
 }
 
 #[derive(Debug, Clone, ValueEnum)]
@@ -18,9 +18,10 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
+use indexmap::IndexMap;
 use clap::ValueEnum;
 
-#[derive(ValueEnum, Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Method {
     #[serde(alias = "Get", alias = "GET")]
@@ -33,6 +34,17 @@ pub enum Method {
     Delete,
     #[serde(alias = "Patch", alias = "PATCH")]
     Patch,
+    #[serde(alias = "Head", alias = "HEAD")]
+    Head,
+    #[serde(alias = "Options", alias = "OPTIONS")]
+    Options,
+    #[serde(alias = "Trace", alias = "TRACE")]
+    Trace,
+    #[serde(alias = "Connect", alias = "CONNECT")]
+    Connect,
+    // any other method, such as PURGE, PROPFIND or REPORT, sent upper case
+    #[serde(untagged)]
+    Extension(String),
 }
 
 #[derive(ValueEnum, Clone, Debug, Serialize, Deserialize)]
@@ -56,7 +68,35 @@ impl std::fmt::Display for Scheme {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
     pub capture: Option<Capture>,
+    // resolved in the order they are declared, so a var can use the ones above it
+    pub vars: Option<IndexMap<String, String>>,
+    // runs before every other run, values captured here become global variables
+    pub setup: Option<Vec<Run>>,
     pub run: Vec<Run>,
+    // runs after every other run, even if they failed
+    pub teardown: Option<Vec<Run>>,
+    // defaults for every run
+    pub cookies: Option<bool>,
+    pub seed_cookies: Option<HashMap<String, String>>,
+    pub follow_redirects: Option<FollowRedirects>,
+    pub proxy: Option<Proxy>,
+    // PEM files, relative to the config file
+    pub ca_cert: Option<std::path::PathBuf>,
+    pub client_cert: Option<std::path::PathBuf>,
+    pub client_key: Option<std::path::PathBuf>,
+    // "host:port" = "IP address", connects there instead of asking DNS, like curl --resolve
+    pub resolve: Option<HashMap<String, String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Proxy {
+    pub url: Option<String>,
+    // hosts, domains and CIDR ranges that are reached directly
+    pub no_proxy: Option<Vec<String>>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // read HTTP_PROXY, HTTPS_PROXY, ALL_PROXY and NO_PROXY, defaults to true
+    pub use_env: Option<bool>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -77,7 +117,17 @@ pub struct CaptureRequest {
     pub port: Option<u16>,
     pub path: Option<String>,
     pub body: Option<String>,
+    pub raw: Option<Raw>,
     pub capture: Option<HashMap<String, String>>,
+    pub assert: Option<Assert>,
+    // reuse the captured values of an earlier invocation while they are younger than the ttl
+    pub cache: Option<Cache>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Cache {
+    // a duration like "30m" or "1h 30m"
+    pub ttl: String,
 }
 
 // holds multiple requests, contents are blocking
@@ -90,10 +140,38 @@ pub struct Run {
     pub scheme: Option<Scheme>,
     pub host: Option<String>,
     pub port: Option<u16>,
+    // send over this socket instead of TCP, host then defaults to "localhost"
+    pub unix_socket: Option<std::path::PathBuf>,
     pub path: Option<String>,
     pub body: Option<String>,
+    // read from a file relative to the config instead of `body`
+    pub body_file: Option<BodyFile>,
+    pub raw: Option<Raw>,
+    pub vars: Option<IndexMap<String, String>>,
+    pub skip_if: Option<String>,
+    pub only_if: Option<String>,
+    // every request in the run is repeated once per row
+    pub data: Option<Vec<HashMap<String, Value>>>,
+    pub data_file: Option<std::path::PathBuf>,
+    // names of runs that have to pass before this one starts
+    pub depends_on: Option<Vec<String>>,
+    // run variables handed to the runs that depend on this one
+    pub exports: Option<Vec<String>>,
+    // keep the cookies set by responses in a jar of the run's own, and send them back
+    pub cookies: Option<bool>,
+    // cookies in the jar before the first request, for every host the run talks to
+    pub seed_cookies: Option<HashMap<String, String>>,
+    pub follow_redirects: Option<FollowRedirects>,
+    // PEM files, relative to the config file. A client_cert may hold the key too
+    pub ca_cert: Option<std::path::PathBuf>,
+    pub client_cert: Option<std::path::PathBuf>,
+    pub client_key: Option<std::path::PathBuf>,
+    // merged over the resolve table of the config root
+    pub resolve: Option<HashMap<String, String>>,
 
     pub request: Vec<Request>,
+    // executed after the requests of the run, even when a breaking assertion ended it early
+    pub teardown: Option<Vec<Request>>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -104,14 +182,172 @@ pub struct Request {
     pub scheme: Option<Scheme>,
     pub host: Option<String>,
     pub port: Option<u16>,
+    pub unix_socket: Option<std::path::PathBuf>,
     pub path: Option<String>,
     pub body: Option<String>,
+    // read from a file relative to the config instead of `body`
+    pub body_file: Option<BodyFile>,
+    // sent as application/x-www-form-urlencoded
+    pub form: Option<HashMap<String, String>>,
+    // sent as multipart/form-data, in this order
+    pub multipart: Option<Vec<MultipartPart>>,
+    // sent as a JSON body, the method defaults to POST
+    pub graphql: Option<GraphQl>,
+    // a JSON-RPC 2.0 call or batch, ids are added and checked, the method defaults to POST
+    pub jsonrpc: Option<JsonRpc>,
+    pub raw: Option<Raw>,
+    pub vars: Option<IndexMap<String, String>>,
+    pub skip_if: Option<String>,
+    pub only_if: Option<String>,
+    pub data: Option<Vec<HashMap<String, Value>>>,
+    pub data_file: Option<std::path::PathBuf>,
+    // repeats the request for every element of a JSON array, either a pointer into the previous
+    // response of the run ("/items") or the name of a captured variable ("items")
+    pub foreach: Option<String>,
+    pub follow_redirects: Option<FollowRedirects>,
+    // reads the response as a stream of events until one of the `sse` limits is reached, captures
+    // and assertions then see an array with the data of every event
+    pub stream: Option<StreamMode>,
+    pub sse: Option<SseOptions>,
 
     pub capture: Option<HashMap<String, String>>,
+    // captured variables that later runs can read through the `global.` prefix
+    pub export: Option<Vec<String>>,
     pub assert: Option<Assert>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// `true` follows up to 10 redirects, `false` none, and a number at most that many
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(untagged)]
+pub enum FollowRedirects {
+    Enabled(bool),
+    Max(usize),
+}
+
+impl FollowRedirects {
+    pub const DEFAULT_MAX: usize = 10;
+
+    pub fn limit(self) -> usize {
+        match self {
+            FollowRedirects::Enabled(true) => Self::DEFAULT_MAX,
+            FollowRedirects::Enabled(false) => 0,
+            FollowRedirects::Max(max) => max,
+        }
+    }
+}
+
+/// `body_file = "order.json"`, or a table to send the file verbatim or set its content type
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum BodyFile {
+    Path(std::path::PathBuf),
+    Options {
+        path: std::path::PathBuf,
+        // defaults to true, false sends the file byte for byte, which binary files need
+        template: Option<bool>,
+        // sent as the Content-Type header, unless the headers set one
+        content_type: Option<String>,
+    },
+}
+
+impl BodyFile {
+    pub fn path(&self) -> &std::path::Path {
+        match self {
+            BodyFile::Path(path) | BodyFile::Options { path, .. } => path,
+        }
+    }
+    pub fn template(&self) -> bool {
+        match self {
+            BodyFile::Path(_) => true,
+            BodyFile::Options { template, .. } => template.unwrap_or(true),
+        }
+    }
+    pub fn content_type(&self) -> Option<&str> {
+        match self {
+            BodyFile::Path(_) => None,
+            BodyFile::Options { content_type, .. } => content_type.as_deref(),
+        }
+    }
+}
+
+/// A GraphQL operation, placeholders are substituted in the query and in string variables
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GraphQl {
+    pub query: Option<String>,
+    // a .graphql file relative to the config file, instead of `query`
+    pub query_file: Option<std::path::PathBuf>,
+    pub variables: Option<HashMap<String, Value>>,
+    pub operation_name: Option<String>,
+}
+
+/// `jsonrpc = { method = "...", params = ... }`, or an array of calls sent as one batch
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum JsonRpc {
+    Call(JsonRpcCall),
+    Batch(Vec<JsonRpcCall>),
+}
+
+impl JsonRpc {
+    pub fn calls(&self) -> &[JsonRpcCall] {
+        match self {
+            JsonRpc::Call(call) => std::slice::from_ref(call),
+            JsonRpc::Batch(calls) => calls,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JsonRpcCall {
+    pub method: String,
+    pub params: Option<Value>,
+    // sent without an id, the server answers nothing for it
+    pub notification: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamMode {
+    Sse,
+}
+
+/// When to stop reading a `stream = "sse"` response, whichever comes first. The server closing the
+/// stream always ends it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SseOptions {
+    pub max_events: Option<usize>,
+    // stops at the first event with this name, together with `until_data` both have to match
+    pub until_event: Option<String>,
+    // regex on the data of the event
+    pub until_data: Option<String>,
+    // e.g. "10s" or "1m 30s", counted from sending the request, defaults to 30 seconds
+    pub timeout: Option<String>,
+}
+
+/// One part of a multipart body, either a text `value` or the contents of a `file`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MultipartPart {
+    pub name: String,
+    pub value: Option<String>,
+    // relative to the config file, sent verbatim
+    pub file: Option<std::path::PathBuf>,
+    // defaults to the name of `file`
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+}
+
+// fields marked raw are sent exactly as written, `{{ }}` placeholders are not substituted
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy)]
+pub struct Raw {
+    #[serde(default)]
+    pub body: bool,
+    #[serde(default)]
+    pub headers: bool,
+    #[serde(default)]
+    pub assert: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Assert {
     #[serde(default)]
     pub breaking: bool,
@@ -120,4 +356,90 @@ pub struct Assert {
     pub subset_matches: Option<HashMap<String, Value>>,
     pub subset_includes: Option<Vec<String>>,
     pub subset_regex: Option<HashMap<String, Value>>,
+    // keyed by cookie name, checked against the Set-Cookie headers of the response
+    pub cookies: Option<HashMap<String, CookieAssert>>,
+    // number of redirects that were followed
+    pub redirect_count: Option<usize>,
+    // URL of the response at the end of the redirect chain
+    pub final_url: Option<String>,
+    // Location header of the final response, for redirects that were not followed
+    pub location: Option<String>,
+    // checked against the leaf certificate of the request's own connection, only min_tls_version
+    // makes a second handshake with the final URL's host
+    pub tls: Option<TlsAssert>,
+    // GraphQL answers errors with 200, these check the `errors` array of the response instead
+    pub graphql_no_errors: Option<bool>,
+    // regex that the message of at least one error has to match
+    pub graphql_error_matches: Option<String>,
+    // pointers into the `result` of a single JSON-RPC call, checked like subset_matches
+    pub jsonrpc_result: Option<HashMap<String, Value>>,
+    pub jsonrpc_error_code: Option<i64>,
+    // events received from a `stream = "sse"` response
+    pub sse_event_count: Option<usize>,
+    // event names that have to arrive in this order, other events may come in between
+    pub sse_events: Option<Vec<String>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TlsAssert {
+    // days left before the leaf certificate expires
+    pub cert_expires_in_days_min: Option<i64>,
+    // regex matched against the leaf certificate's issuer, e.g. "CN=Example CA"
+    pub issuer_matches: Option<String>,
+    // DNS names or IP addresses the leaf certificate must list
+    pub subject_alt_names_include: Option<Vec<String>>,
+    pub min_tls_version: Option<TlsVersion>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum TlsVersion {
+    #[serde(rename = "1.2", alias = "TLSv1.2")]
+    Tls12,
+    #[serde(rename = "1.3", alias = "TLSv1.3")]
+    Tls13,
+}
+
+impl std::fmt::Display for TlsVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsVersion::Tls12 => write!(f, "TLSv1.2"),
+            TlsVersion::Tls13 => write!(f, "TLSv1.3"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CookieAssert {
+    // defaults to true, false asserts the cookie is not set
+    pub present: Option<bool>,
+    pub value: Option<String>,
+    pub secure: Option<bool>,
+    pub http_only: Option<bool>,
+    pub same_site: Option<SameSite>,
+    // a session cookie has neither Expires nor Max-Age
+    pub session: Option<bool>,
+    // bounds in seconds on how long until the cookie expires
+    pub expires_in_min: Option<i64>,
+    pub expires_in_max: Option<i64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSite {
+    #[serde(alias = "Strict")]
+    Strict,
+    #[serde(alias = "Lax")]
+    Lax,
+    #[serde(alias = "None")]
+    None,
+}
+
+impl std::fmt::Display for SameSite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SameSite::Strict => write!(f, "Strict"),
+            SameSite::Lax => write!(f, "Lax"),
+            SameSite::None => write!(f, "None"),
+        }
+    }
 }
@@ -15,33 +15,64 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, MutexGuard},
+};
 
-use regex::Regex;
+use indexmap::IndexMap;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde_json::Value;
+
+use crate::{
+    models::{plan::ClientSettings, test_data::GeneratedValue},
+    utils::{
+        cookies::RunCookies,
+        expression::{self, Scope},
+    },
+};
 
 #[derive(Debug)]
 pub struct Global {
     pub env_variables: HashMap<String, String>,
     pub variables: HashMap<String, String>,
-    matcher: Regex,
+    // Global is frozen behind an Arc once the capture phase is done, so the rng needs to be
+    // lockable to hand out seeds to each RunState
+    rng: Mutex<StdRng>,
 }
 
 impl Global {
     pub fn new() -> Self {
+        Self::with_rng(StdRng::from_os_rng())
+    }
+    /// Same as `new()`, but every random value produced by template functions is reproducible
+    pub fn seeded(seed: u64) -> Self {
+        Self::with_rng(StdRng::seed_from_u64(seed))
+    }
+    fn with_rng(rng: StdRng) -> Self {
         Self {
             env_variables: HashMap::new(),
             variables: HashMap::new(),
-            matcher: Regex::new(r"\{\{\s*(.*?)\s*\}\}").expect("Failed to compile regex"),
+            rng: Mutex::new(rng),
         }
     }
+    fn fork_rng(&self) -> StdRng {
+        let mut rng = self.rng.lock().expect("rng mutex poisoned");
+        StdRng::seed_from_u64(rng.random())
+    }
     pub fn substitute_values_in_text(&self, input: &str) -> String {
-        self.matcher
-            .replace_all(input, |caps: &regex::Captures| {
-                let key = &caps[1].trim();
-
-                self.resolve(key).unwrap_or(&caps[0]).to_string()
-            })
-            .to_string()
+        let mut scope = GlobalScope {
+            global: self,
+            rng: self.rng.lock().expect("rng mutex poisoned"),
+        };
+        expression::render(input, &mut scope)
+    }
+    /// Resolves the suite `[vars]` in declaration order, so a var can use the ones above it
+    pub fn add_variables(&mut self, vars: &IndexMap<String, String>) {
+        for (key, value) in vars {
+            let value = self.substitute_values_in_text(value);
+            self.variables.insert(key.clone(), value);
+        }
     }
     pub fn resolve(&self, key: &str) -> Option<&str> {
         if let Some(identifier) = key.strip_prefix("env.") {
@@ -64,15 +95,64 @@ impl Default for Global {
     }
 }
 
+struct GlobalScope<'a> {
+    global: &'a Global,
+    rng: MutexGuard<'a, StdRng>,
+}
+
+impl Scope for GlobalScope<'_> {
+    fn lookup(&self, key: &str) -> Option<String> {
+        self.global.resolve(key).map(|v| v.to_string())
+    }
+    fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+}
+
 pub struct RunState {
     pub run_variables: HashMap<String, String>,
+    // cleared at the start of every request, holds the request level `vars` table
+    pub request_variables: HashMap<String, String>,
+    // fields of the data row the current request was expanded from, reachable as `row.field`
+    pub row_variables: HashMap<String, String>,
+    // `item`, `item.field` and `index` of the current foreach iteration
+    item_variables: HashMap<String, String>,
+    // json body of the most recent response in the run, for `foreach = "/pointer"`
+    pub last_response: Option<Value>,
+    // values produced by non deterministic template functions since the last drain
+    pub generated: Vec<GeneratedValue>,
+    // request exports of earlier runs and of this one, they shadow `Global::variables`
+    pub global_exports: HashMap<String, String>,
+    // what this run exported itself
+    pub exported: HashMap<String, String>,
+    // names of the run variables captured from responses, the ones a setup run makes global
+    pub captured: HashSet<String>,
+    // the run's cookie jar, when it has cookies enabled
+    pub cookies: Option<RunCookies>,
+    // the run's certificates and resolve overrides, reused when a request inspects its tls connection
+    pub client: ClientSettings,
+    // id of the last JSON-RPC call the run sent
+    jsonrpc_id: u64,
     pub global: Arc<Global>,
+    rng: StdRng,
 }
 
 impl RunState {
     pub fn new(global: Arc<Global>) -> Self {
         Self {
             run_variables: HashMap::new(),
+            request_variables: HashMap::new(),
+            row_variables: HashMap::new(),
+            item_variables: HashMap::new(),
+            last_response: None,
+            generated: Vec::new(),
+            global_exports: HashMap::new(),
+            exported: HashMap::new(),
+            captured: HashSet::new(),
+            cookies: None,
+            client: ClientSettings::default(),
+            jsonrpc_id: 0,
+            rng: global.fork_rng(),
             global,
         }
     }
@@ -82,14 +162,34 @@ impl RunState {
         }
 
         if let Some(identifier) = key.strip_prefix("global.") {
-            return self.global.variables.get(identifier).map(|v| v.as_str());
+            return self
+                .global_exports
+                .get(identifier)
+                .or_else(|| self.global.variables.get(identifier))
+                .map(|v| v.as_str());
         }
 
         if let Some(identifier) = key.strip_prefix("run.") {
             return self.run_variables.get(identifier).map(|v| v.as_str());
         }
 
-        if let Some(value) = self.run_variables.get(key) {
+        if let Some(identifier) = key.strip_prefix("request.") {
+            return self.request_variables.get(identifier).map(|v| v.as_str());
+        }
+
+        if let Some(identifier) = key.strip_prefix("row.") {
+            return self.row_variables.get(identifier).map(|v| v.as_str());
+        }
+
+        if key == "item" || key == "index" || key.starts_with("item.") {
+            return self.item_variables.get(key).map(|v| v.as_str());
+        }
+
+        if let Some(value) = self.request_variables.get(key) {
+            Some(value.as_str())
+        } else if let Some(value) = self.run_variables.get(key) {
+            Some(value.as_str())
+        } else if let Some(value) = self.global_exports.get(key) {
             Some(value.as_str())
         } else if let Some(value) = self.global.variables.get(key) {
             Some(value.as_str())
@@ -100,14 +200,85 @@ impl RunState {
         }
     }
 
-    pub fn substitute_values_in_text(&self, input: &str) -> String {
-        self.global.matcher
-            .replace_all(input, |caps: &regex::Captures| {
-                let key = &caps[1].trim();
+    pub fn substitute_values_in_text(&mut self, input: &str) -> String {
+        expression::render(input, self)
+    }
+
+    /// Resolves a run's `vars` in declaration order, like `Global::add_variables`
+    pub fn add_run_variables(&mut self, vars: &IndexMap<String, String>) {
+        for (key, value) in vars {
+            let value = self.substitute_values_in_text(value);
+            self.run_variables.insert(key.clone(), value);
+        }
+    }
+    pub fn add_request_variables(&mut self, vars: &IndexMap<String, String>) {
+        for (key, value) in vars {
+            let value = self.substitute_values_in_text(value);
+            self.request_variables.insert(key.clone(), value);
+        }
+    }
+
+    /// Exposes a foreach element as `item`, its nested fields as `item.a.b` (array elements by
+    /// position, `item.0`), and its position as `index`
+    pub fn set_item(&mut self, index: usize, item: &Value) {
+        self.item_variables.clear();
+        self.item_variables
+            .insert("index".to_string(), index.to_string());
+
+        let mut stack = vec![("item".to_string(), item)];
+        while let Some((key, value)) = stack.pop() {
+            match value {
+                Value::Object(obj) => {
+                    for (field, value) in obj {
+                        stack.push((format!("{key}.{field}"), value));
+                    }
+                }
+                Value::Array(arr) => {
+                    for (position, value) in arr.iter().enumerate() {
+                        stack.push((format!("{key}.{position}"), value));
+                    }
+                }
+                _ => {}
+            }
+            let value = match value {
+                Value::String(s) => s.clone(),
+                v => v.to_string(),
+            };
+            self.item_variables.insert(key, value);
+        }
+    }
+
+    pub fn clear_item(&mut self) {
+        self.item_variables.clear();
+    }
+
+    /// Makes a captured run variable visible to later runs as `global.key`
+    pub fn export(&mut self, key: &str) {
+        if let Some(value) = self.run_variables.get(key) {
+            self.exported.insert(key.to_string(), value.clone());
+            self.global_exports.insert(key.to_string(), value.clone());
+        }
+    }
 
-                self.resolve(key).unwrap_or(&caps[0]).to_string()
-            })
-            .to_string()
+    /// Ids count up from 1 within a run
+    pub fn next_jsonrpc_id(&mut self) -> u64 {
+        self.jsonrpc_id += 1;
+        self.jsonrpc_id
+    }
+}
+
+impl Scope for RunState {
+    fn lookup(&self, key: &str) -> Option<String> {
+        self.resolve(key).map(|v| v.to_string())
+    }
+    fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+    fn record(&mut self, expression: &str, value: &str) {
+        self.generated.push(GeneratedValue {
+            expression: expression.to_string(),
+            value: value.to_string(),
+        });
     }
 }
 
@@ -135,4 +306,83 @@ mod tests {
 
         assert_eq!("Hello,_World!".to_string(), output);
     }
+
+    #[test]
+    fn test_request_scope_shadows_run_scope() {
+        let mut global = Global::new();
+        global
+            .variables
+            .insert("id".to_string(), "global".to_string());
+
+        let mut state = RunState::new(Arc::new(global));
+        state
+            .run_variables
+            .insert("id".to_string(), "run".to_string());
+        state
+            .request_variables
+            .insert("id".to_string(), "request".to_string());
+
+        let output = state.substitute_values_in_text("{{id}} {{run.id}} {{global.id}} {{request.id}}");
+
+        assert_eq!("request run global request", output);
+    }
+
+    #[test]
+    fn test_template_functions() {
+        let mut global = Global::seeded(7);
+        global
+            .variables
+            .insert("user".to_string(), "alice".to_string());
+        let global = Arc::new(global);
+
+        let mut first = RunState::new(global.clone());
+        let output = first.substitute_values_in_text(
+            "{{ base64(user) }} {{ urlencode('a b/c') }} {{ random_int(5, 5) }} {{ nope() }}",
+        );
+        assert_eq!("YWxpY2U= a%20b%2Fc 5 {{ nope() }}", output);
+        let output = first.substitute_values_in_text(
+            r#"{{ missing | default("anon") | upper }} {{ user | upper }} {{ missing | upper }}"#,
+        );
+        assert_eq!("ANON ALICE {{ missing | upper }}", output);
+        assert_eq!(first.generated.len(), 1);
+
+        let uuid = first.substitute_values_in_text("{{ uuid() }}");
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+
+        let reseeded = Arc::new(Global::seeded(7));
+        let mut second = RunState::new(reseeded);
+        second.substitute_values_in_text("{{ random_int(5, 5) }}");
+        assert_eq!(uuid, second.substitute_values_in_text("{{ uuid() }}"));
+    }
+
+    #[test]
+    fn test_foreach_item() {
+        let mut state = RunState::new(Arc::new(Global::new()));
+        let item = serde_json::json!({ "a": { "b": "deep" }, "tags": ["x", { "y": 1 }], "n": null });
+        state.set_item(3, &item);
+
+        let output = state.substitute_values_in_text(
+            "{{index}} {{item.a.b}} {{item.tags.0}} {{item.tags.1.y}} {{item.n}} {{item.a}}",
+        );
+        assert_eq!(r#"3 deep x 1 null {"b":"deep"}"#, output);
+
+        state.set_item(0, &serde_json::json!("plain"));
+        assert_eq!("plain {{item.a.b}}", state.substitute_values_in_text("{{item}} {{item.a.b}}"));
+        state.clear_item();
+        assert_eq!("{{item}}", state.substitute_values_in_text("{{item}}"));
+    }
+
+    #[test]
+    fn test_escaped_braces() {
+        let mut global = Global::new();
+        global
+            .variables
+            .insert("name".to_string(), "alice".to_string());
+        let mut state = RunState::new(Arc::new(global));
+
+        let output = state.substitute_values_in_text(r"\{{name}} {{name}}");
+
+        assert_eq!("{{name}} alice", output);
+    }
 }
@@ -15,25 +15,40 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 
-use std::{collections::HashMap, path::{Path, PathBuf}};
+use std::{collections::{BTreeMap, HashMap}, net::IpAddr, path::{Path, PathBuf}, time::Duration};
 
-use crate::models::{config::{Assert, Config, Request, Run, Scheme}, error::AlixtError};
+use crate::models::{config::{Assert, Config, FollowRedirects, GraphQl, JsonRpc, MultipartPart, Proxy, Raw, Request, Run, Scheme, SseOptions, StreamMode}, error::AlixtError};
+use crate::utils::{data::{self, Row}, expression, json};
 
 use crate::models::config::Method as ConfigMethod;
+use indexmap::IndexMap;
+use regex::Regex;
 use reqwest::Method;
+use serde_json::Value;
 
 
 #[derive(Default)]
 pub struct TestPlan {
     pub capture: Option<CapturePlan>,
+    pub proxy: Option<Proxy>,
+    // used by the capture requests, and by every run that does not set its own
+    pub client: ClientSettings,
+    pub vars: Option<IndexMap<String, String>>,
+    pub setup: Vec<RunPlan>,
     pub runs: Vec<RunPlan>,
+    pub teardown: Vec<RunPlan>,
 }
 
 impl TestPlan {
     pub fn new() -> Self {
         Self {
             capture: None,
+            proxy: None,
+            client: ClientSettings::default(),
+            vars: None,
+            setup: Vec::new(),
             runs: Vec::new(),
+            teardown: Vec::new(),
         }
     }
 
@@ -41,19 +56,272 @@ impl TestPlan {
         let mut config = config;
         let mut plan = TestPlan::new();
         plan.capture = CapturePlan::from_config(&mut config, working_dir)?;
+        plan.vars = config.vars.take();
+        if let Some(vars) = &plan.vars {
+            check_templates(vars.values(), "[vars]")?;
+        }
+        plan.proxy = config.proxy.take();
+        if let Some(proxy) = &plan.proxy {
+            check_templates(
+                proxy.url.iter().chain(&proxy.username).chain(&proxy.password),
+                "[proxy]",
+            )?;
+        }
+        plan.client.tls = TlsFiles::resolve(
+            config.ca_cert.take(),
+            config.client_cert.take(),
+            config.client_key.take(),
+            &TlsFiles::default(),
+            working_dir,
+        )
+        .map_err(|e| AlixtError::Config(format!("{e} in the config root")))?;
+        if let Some(table) = config.resolve.take() {
+            plan.client
+                .add_resolve_table(&table)
+                .map_err(|e| AlixtError::Config(format!("{e} in the config root")))?;
+        }
+        let suite = SuiteDefaults {
+            client: plan.client.clone(),
+            cookies: config.cookies.take(),
+            seed_cookies: config.seed_cookies.take(),
+            follow_redirects: config.follow_redirects,
+        };
         let config = config;
 
-        for run in config.run {
-            plan.runs.push(RunPlan::from_run(run)?);
+        for run in config.setup.unwrap_or_default() {
+            plan.setup.push(RunPlan::from_run(run, &suite, working_dir)?);
+        }
+        let mut dependencies = Vec::new();
+        for mut run in config.run {
+            dependencies.push(run.depends_on.take().unwrap_or_default());
+            plan.runs.push(RunPlan::from_run(run, &suite, working_dir)?);
+        }
+        for run in config.teardown.unwrap_or_default() {
+            plan.teardown.push(RunPlan::from_run(run, &suite, working_dir)?);
         }
+        plan.resolve_dependencies(dependencies)?;
         Ok(plan)
     }
+
+    /// Applies `--resolve` overrides on top of every resolve table of the config
+    pub fn override_resolve(&mut self, overrides: &[(String, IpAddr)]) {
+        let runs = self.setup.iter_mut().chain(&mut self.runs).chain(&mut self.teardown);
+        for client in std::iter::once(&mut self.client).chain(runs.map(|run| &mut run.client)) {
+            client.resolve.extend(overrides.iter().cloned());
+        }
+    }
+
+    /// Turns the `depends_on` names of every run into indices into `runs`, rejecting unknown or
+    /// ambiguous names and cycles
+    fn resolve_dependencies(&mut self, dependencies: Vec<Vec<String>>) -> Result<(), AlixtError> {
+        for (index, names) in dependencies.into_iter().enumerate() {
+            for name in names {
+                let mut matching = self.runs.iter().enumerate().filter(|(_, run)| run.name == name);
+                let Some((dependency, _)) = matching.next() else {
+                    return Err(AlixtError::Config(format!(
+                        "Run '{}' depends on unknown run '{name}'",
+                        self.runs[index].name
+                    )));
+                };
+                if matching.next().is_some() {
+                    return Err(AlixtError::Config(format!(
+                        "Run '{}' depends on '{name}', but more than one run has that name",
+                        self.runs[index].name
+                    )));
+                }
+                if !self.runs[index].depends_on.contains(&dependency) {
+                    self.runs[index].depends_on.push(dependency);
+                }
+            }
+        }
+
+        // Kahn's algorithm, whatever can not be ordered is part of a cycle
+        let mut remaining: Vec<usize> = self.runs.iter().map(|run| run.depends_on.len()).collect();
+        let mut ready: Vec<usize> = (0..self.runs.len()).filter(|&i| remaining[i] == 0).collect();
+        let mut ordered = 0;
+        while let Some(done) = ready.pop() {
+            ordered += 1;
+            for (index, run) in self.runs.iter().enumerate() {
+                if run.depends_on.contains(&done) {
+                    remaining[index] -= 1;
+                    if remaining[index] == 0 {
+                        ready.push(index);
+                    }
+                }
+            }
+        }
+        if ordered < self.runs.len() {
+            let cycle: Vec<String> = self
+                .runs
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| remaining[*index] > 0)
+                .map(|(_, run)| format!("'{}'", run.name))
+                .collect();
+            return Err(AlixtError::Config(format!(
+                "Runs depend on each other in a cycle: {}",
+                cycle.join(", ")
+            )));
+        }
+        self.resolve_global_sources();
+        Ok(())
+    }
+
+    /// Request exports of a run are visible to every later run in the plan, and to runs that
+    /// depend on it, no matter how many runs execute in parallel. An earlier run that itself
+    /// waits on this one is the exception, since it can not have finished first.
+    fn resolve_global_sources(&mut self) {
+        let exporting: Vec<bool> = self.runs.iter().map(RunPlan::has_global_exports).collect();
+        let mut waits: Vec<Vec<usize>> = self.runs.iter().map(|run| run.depends_on.clone()).collect();
+        for index in 0..self.runs.len() {
+            let earlier_exporters = (0..index).filter(|&earlier| exporting[earlier]);
+            for earlier in earlier_exporters {
+                if !waits_on(&waits, earlier, index) {
+                    waits[index].push(earlier);
+                }
+            }
+        }
+        for (index, run) in self.runs.iter_mut().enumerate() {
+            run.global_sources = (0..exporting.len())
+                .filter(|&other| exporting[other] && other != index && waits_on(&waits, index, other))
+                .collect();
+        }
+    }
+}
+
+/// Whether `from` has to wait for `to`, directly or through other runs
+fn waits_on(waits: &[Vec<usize>], from: usize, to: usize) -> bool {
+    let mut seen = vec![false; waits.len()];
+    let mut stack = vec![from];
+    while let Some(current) = stack.pop() {
+        for &next in &waits[current] {
+            if next == to {
+                return true;
+            }
+            if !seen[next] {
+                seen[next] = true;
+                stack.push(next);
+            }
+        }
+    }
+    false
+}
+
+/// Settings of the config root that every run inherits unless it sets its own
+struct SuiteDefaults {
+    cookies: Option<bool>,
+    seed_cookies: Option<HashMap<String, String>>,
+    follow_redirects: Option<FollowRedirects>,
+    client: ClientSettings,
+}
+
+/// Everything a client is built from, runs with equal settings share a client
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct ClientSettings {
+    pub tls: TlsFiles,
+    // host names that connect to these addresses instead of asking DNS
+    pub resolve: BTreeMap<String, IpAddr>,
+    // set per request, see `Clients::for_run`
+    pub unix_socket: Option<PathBuf>,
+}
+
+impl ClientSettings {
+    /// Adds the entries of a `resolve` table, replacing inherited ones for the same host. Keys are
+    /// `host` or `host:port` as in curl's --resolve, but the override covers every port of the
+    /// host, so a table can not send one host to two addresses.
+    fn add_resolve_table(&mut self, table: &HashMap<String, String>) -> Result<(), String> {
+        let mut entries = BTreeMap::new();
+        for (host, address) in table {
+            let (host, address) = parse_resolve_entry(host, address)?;
+            if let Some(other) = entries.insert(host.clone(), address)
+                && other != address
+            {
+                return Err(format!(
+                    "resolve sends '{host}' to both {other} and {address}, one host can only have one address"
+                ));
+            }
+        }
+        self.resolve.extend(entries);
+        Ok(())
+    }
+}
+
+/// Splits a `host[:port]` and an IP address into a resolve override
+pub fn parse_resolve_entry(host: &str, address: &str) -> Result<(String, IpAddr), String> {
+    let host = match host.rsplit_once(':') {
+        Some((name, port)) if port.parse::<u16>().is_ok() => name,
+        _ => host,
+    };
+    if host.is_empty() {
+        return Err("resolve has an entry without a host".to_string());
+    }
+    let ip = address
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .map_err(|_| format!("resolve address '{address}' for '{host}' is not an IP address"))?;
+    Ok((host.to_ascii_lowercase(), ip))
+}
+
+/// Certificate files a client is built with
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct TlsFiles {
+    pub ca_cert: Option<PathBuf>,
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+}
+
+impl TlsFiles {
+    /// Resolves the paths against the config directory, falling back to `inherited` for the ones
+    /// not set. A client_key always belongs to the client_cert set next to it.
+    fn resolve(
+        ca_cert: Option<PathBuf>,
+        client_cert: Option<PathBuf>,
+        client_key: Option<PathBuf>,
+        inherited: &TlsFiles,
+        working_dir: &Path,
+    ) -> Result<TlsFiles, String> {
+        let existing = |path: PathBuf, field: &str| {
+            let full_path = working_dir.join(&path);
+            if full_path.exists() {
+                Ok(full_path)
+            } else {
+                Err(format!("{field} file not found: {path:?} (looked in {full_path:?})"))
+            }
+        };
+        if client_key.is_some() && client_cert.is_none() {
+            return Err("client_key is set without a client_cert".to_string());
+        }
+
+        let ca_cert = match ca_cert {
+            Some(path) => Some(existing(path, "ca_cert")?),
+            None => inherited.ca_cert.clone(),
+        };
+        let (client_cert, client_key) = match client_cert {
+            Some(cert) => (
+                Some(existing(cert, "client_cert")?),
+                client_key.map(|key| existing(key, "client_key")).transpose()?,
+            ),
+            None => (inherited.client_cert.clone(), inherited.client_key.clone()),
+        };
+        Ok(TlsFiles {
+            ca_cert,
+            client_cert,
+            client_key,
+        })
+    }
 }
 
 pub struct CapturePlan {
     pub env_file: Option<PathBuf>,
     pub environment_variables: Option<HashMap<String, String>>,
-    pub requests: Option<Vec<ExecuteRequest>>,
+    pub requests: Option<Vec<CaptureStep>>,
+}
+
+pub struct CaptureStep {
+    pub request: ExecuteRequest,
+    // how long the captured values may be reused by later invocations
+    pub cache_ttl: Option<Duration>,
 }
 
 impl CapturePlan {
@@ -73,19 +341,51 @@ impl CapturePlan {
             None
         };
 
+        let redirect_limit = config
+            .follow_redirects
+            .map_or(FollowRedirects::DEFAULT_MAX, FollowRedirects::limit);
         let requests = if let Some(requests) = capture.request {
             let mut reqs = Vec::new();
 
             for request in requests {
-                reqs.push(ExecuteRequest {
+                let cache = request.cache;
+                let request = ExecuteRequest {
                     name: request.name.unwrap_or("".to_string()),
                     url: ExecuteRequest::_format_url(request.scheme, request.host, request.port, request.path),
-                    method: ExecuteRequest::_convert_method(request.method),
+                    method: ExecuteRequest::_convert_method(request.method)?,
                     body: request.body,
+                    body_bytes: None,
+                    form: None,
+                    multipart: None,
+                    graphql: None,
+                    jsonrpc: None,
+                    content_type: None,
+                    sse: None,
+                    raw: request.raw.unwrap_or_default(),
                     headers: request.headers,
+                    vars: None,
+                    skip_if: None,
+                    only_if: None,
+                    row: None,
+                    foreach: None,
+                    unix_socket: None,
+                    redirect_limit,
                     capture: request.capture,
-                    assert: None,
-                });
+                    export: Vec::new(),
+                    assert: request.assert,
+                };
+                request.validate_templates()?;
+                request.validate_head()?;
+                let cache_ttl = match cache {
+                    Some(cache) => Some(humantime::parse_duration(&cache.ttl).map_err(|e| {
+                        AlixtError::Config(format!(
+                            "Invalid cache ttl '{}' for capture request '{}': {e}",
+                            cache.ttl, request.name
+                        ))
+                    })?),
+                    None => None,
+                };
+                reqs.push(CaptureStep { request, cache_ttl });
             }
             Some(reqs)
         } else {
@@ -101,83 +401,494 @@ impl CapturePlan {
 
 pub struct RunPlan {
     pub name: String,
+    pub vars: Option<IndexMap<String, String>>,
+    pub skip_if: Option<String>,
+    pub only_if: Option<String>,
     pub requests: Vec<ExecuteRequest>,
+    pub teardown: Vec<ExecuteRequest>,
+    // indices into `TestPlan::runs`
+    pub depends_on: Vec<usize>,
+    pub exports: Vec<String>,
+    // runs whose request exports this run sees through `global.`, in plan order. The run waits
+    // for them, but unlike depends_on does not care whether they passed
+    pub global_sources: Vec<usize>,
+    pub cookies: bool,
+    pub seed_cookies: HashMap<String, String>,
+    pub client: ClientSettings,
 }
 
 impl RunPlan {
     pub fn new(name: String) -> Self {
         Self {
             name,
-            requests: Vec::new()
+            vars: None,
+            skip_if: None,
+            only_if: None,
+            requests: Vec::new(),
+            teardown: Vec::new(),
+            depends_on: Vec::new(),
+            exports: Vec::new(),
+            global_sources: Vec::new(),
+            cookies: false,
+            seed_cookies: HashMap::new(),
+            client: ClientSettings::default(),
         }
     }
     
-    fn from_run(run: Run) -> Result<RunPlan, AlixtError> {
-        let mut run_plan = RunPlan::new(run.name);
+    pub fn has_global_exports(&self) -> bool {
+        self.requests.iter().chain(&self.teardown).any(|request| !request.export.is_empty())
+    }
 
-        for mut request in run.request {
-            // inheritance checks
-            if request.headers.is_none() && run.headers.is_some() {
-                request.headers = run.headers.clone();
-            }
-            if request.method.is_none() {
-                if run.method.is_none() {
-                    return Err(AlixtError::Config(format!(
-                        "No default or explicit method present for {}",
-                        request.name
-                    )));
-                }
-                request.method = run.method.clone();
-            }
-            if request.scheme.is_none() {
-                if run.scheme.is_none() {
-                    return Err(AlixtError::Config(format!(
-                        "No default or explicit scheme present for {}",
-                        request.name
-                    )));
+    fn from_run(
+        mut run: Run,
+        suite: &SuiteDefaults,
+        working_dir: &Path,
+    ) -> Result<RunPlan, AlixtError> {
+        let run_rows = load_rows(
+            run.data.take(),
+            run.data_file.take(),
+            working_dir,
+            &format!("run '{}'", run.name),
+        )?;
+        let mut run_plan = RunPlan::new(run.name.clone());
+        if run.depends_on.is_some() {
+            return Err(AlixtError::Config(format!(
+                "depends_on is only supported in [[run]], found in '{}'",
+                run.name
+            )));
+        }
+        run_plan.exports = run.exports.take().unwrap_or_default();
+        check_body_kinds(
+            &format!("Run '{}'", run.name),
+            &[("body", run.body.is_some()), ("body_file", run.body_file.is_some())],
+        )?;
+
+        // seeds of the run replace suite seeds with the same name
+        let mut seed_cookies = suite.seed_cookies.clone().unwrap_or_default();
+        seed_cookies.extend(run.seed_cookies.take().unwrap_or_default());
+        check_templates(seed_cookies.values(), &format!("seed_cookies of run '{}'", run_plan.name))?;
+        run_plan.cookies = run.cookies.or(suite.cookies).unwrap_or(!seed_cookies.is_empty());
+        if !run_plan.cookies && !seed_cookies.is_empty() {
+            return Err(AlixtError::Config(format!(
+                "Run '{}' has seed_cookies but cookies = false",
+                run_plan.name
+            )));
+        }
+        run_plan.seed_cookies = seed_cookies;
+        if run.follow_redirects.is_none() {
+            run.follow_redirects = suite.follow_redirects;
+        }
+        run_plan.client.tls = TlsFiles::resolve(
+            run.ca_cert.take(),
+            run.client_cert.take(),
+            run.client_key.take(),
+            &suite.client.tls,
+            working_dir,
+        )
+        .map_err(|e| AlixtError::Config(format!("{e} in run '{}'", run_plan.name)))?;
+        run_plan.client.resolve = suite.client.resolve.clone();
+        if let Some(table) = run.resolve.take() {
+            run_plan
+                .client
+                .add_resolve_table(&table)
+                .map_err(|e| AlixtError::Config(format!("{e} in run '{}'", run_plan.name)))?;
+        }
+        if let Some(vars) = &run.vars {
+            check_templates(vars.values(), &format!("vars of run '{}'", run_plan.name))?;
+        }
+        check_templates(run.skip_if.iter().chain(&run.only_if), &format!("conditions of run '{}'", run_plan.name))?;
+        run_plan.vars = run.vars.take();
+        run_plan.skip_if = run.skip_if.take();
+        run_plan.only_if = run.only_if.take();
+
+        let mut planned = Vec::new();
+        let requests = std::mem::take(&mut run.request);
+        for mut request in requests {
+            apply_run_defaults(&run, &mut request)?;
+            request.unix_socket = request.unix_socket.take().map(|path| working_dir.join(path));
+
+            let rows = load_rows(
+                request.data.take(),
+                request.data_file.take(),
+                working_dir,
+                &format!("request '{}'", request.name),
+            )?;
+            planned.push((ExecuteRequest::from_request(request, working_dir)?, rows));
+        }
+
+        for mut request in run.teardown.take().unwrap_or_default() {
+            apply_run_defaults(&run, &mut request)?;
+            request.unix_socket = request.unix_socket.take().map(|path| working_dir.join(path));
+            run_plan.teardown.push(ExecuteRequest::from_request(request, working_dir)?);
+        }
+
+        // a run level data table repeats the whole sequence of requests once per row, its index
+        // is named before the one of the request's own rows
+        let run_rows: Vec<(Option<usize>, Row)> = match run_rows {
+            Some(rows) => rows.into_iter().enumerate().map(|(index, row)| (Some(index), row)).collect(),
+            None => vec![(None, Row::new())],
+        };
+        for (index, run_row) in run_rows {
+            for (request, rows) in &planned {
+                let mut request = request.clone();
+                if let Some(index) = index {
+                    request.name = format!("{} [run row {index}]", request.name);
+                    request.row = Some(run_row.clone());
                 }
-                request.scheme = run.scheme.clone();
-            }
-            if request.host.is_none() {
-                if run.host.is_none() {
-                    return Err(AlixtError::Config(format!(
-                        "No default or explicit host present for {}",
-                        request.name
-                    )));
+                match rows {
+                    Some(rows) => run_plan.requests.extend(request.expand(rows.clone())),
+                    None => run_plan.requests.push(request),
                 }
-                request.host = run.host.clone();
             }
-            if request.port.is_none() {
-                request.port = run.port;
+        }
+
+        Ok(run_plan)
+    }
+}
+
+/// Fills in every field the request left out with the defaults of its run
+fn apply_run_defaults(run: &Run, request: &mut Request) -> Result<(), AlixtError> {
+    if request.headers.is_none() && run.headers.is_some() {
+        request.headers = run.headers.clone();
+    }
+    if request.unix_socket.is_none() {
+        request.unix_socket = run.unix_socket.clone();
+    }
+    // the URL of a request over a unix socket only matters for the Host header
+    if request.unix_socket.is_some() {
+        if request.scheme.is_none() && run.scheme.is_none() {
+            request.scheme = Some(Scheme::Http);
+        }
+        if request.host.is_none() && run.host.is_none() {
+            request.host = Some("localhost".to_string());
+        }
+    }
+    if request.method.is_none() && run.method.is_none() && (request.graphql.is_some() || request.jsonrpc.is_some()) {
+        request.method = Some(ConfigMethod::Post);
+    }
+    if request.method.is_none() {
+        if run.method.is_none() {
+            return Err(AlixtError::Config(format!(
+                "No default or explicit method present for {}",
+                request.name
+            )));
+        }
+        request.method = run.method.clone();
+    }
+    if request.scheme.is_none() {
+        if run.scheme.is_none() {
+            return Err(AlixtError::Config(format!(
+                "No default or explicit scheme present for {}",
+                request.name
+            )));
+        }
+        request.scheme = run.scheme.clone();
+    }
+    if request.host.is_none() {
+        if run.host.is_none() {
+            return Err(AlixtError::Config(format!(
+                "No default or explicit host present for {}",
+                request.name
+            )));
+        }
+        request.host = run.host.clone();
+    }
+    if request.port.is_none() {
+        request.port = run.port;
+    }
+    if request.path.is_none() {
+        // if neither the request nor run specify a path, it defaults to "/"
+        request.path = run.path.clone();
+    }
+    let has_body = check_body_kinds(
+        &format!("Request '{}'", request.name),
+        &[
+            ("body", request.body.is_some()),
+            ("body_file", request.body_file.is_some()),
+            ("form", request.form.is_some()),
+            ("multipart", request.multipart.is_some()),
+            ("graphql", request.graphql.is_some()),
+            ("jsonrpc", request.jsonrpc.is_some()),
+        ],
+    )?;
+    if !has_body {
+        request.body = run.body.clone();
+        request.body_file = run.body_file.clone();
+        check_body_kinds(
+            &format!("Request '{}', through run '{}',", request.name, run.name),
+            &[("body", request.body.is_some()), ("body_file", request.body_file.is_some())],
+        )?;
+    }
+    if request.raw.is_none() {
+        request.raw = run.raw;
+    }
+    if request.follow_redirects.is_none() {
+        request.follow_redirects = run.follow_redirects;
+    }
+    Ok(())
+}
+
+/// Errors when more than one kind of body is set, `owner` starts the message. Returns whether any
+/// body is set
+fn check_body_kinds(owner: &str, bodies: &[(&str, bool)]) -> Result<bool, AlixtError> {
+    let set: Vec<&str> = bodies.iter().filter(|(_, set)| *set).map(|(kind, _)| *kind).collect();
+    if set.len() > 1 {
+        return Err(AlixtError::Config(format!(
+            "{owner} has {}, only one kind of body can be used",
+            set.join(" and ")
+        )));
+    }
+    Ok(!set.is_empty())
+}
+
+/// The limits of a `stream = "sse"` request
+#[derive(Clone)]
+pub struct SseStream {
+    pub max_events: Option<usize>,
+    pub until_event: Option<String>,
+    pub until_data: Option<Regex>,
+    pub timeout: Duration,
+}
+
+impl SseStream {
+    const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    fn from_config(
+        stream: Option<StreamMode>,
+        options: Option<SseOptions>,
+        assert: Option<&Assert>,
+        request: &str,
+    ) -> Result<Option<Self>, AlixtError> {
+        if stream != Some(StreamMode::Sse) {
+            let asserts_events = assert
+                .is_some_and(|assert| assert.sse_event_count.is_some() || assert.sse_events.is_some());
+            if options.is_some() || asserts_events {
+                return Err(AlixtError::Config(format!(
+                    "Request '{request}' has sse options or assertions but does not set stream = \"sse\""
+                )));
             }
-            if request.path.is_none() {
-                // if neither the request nor run specify a path, it defaults to "/"
-                request.path = run.path.clone();
+            return Ok(None);
+        }
+        let options = options.unwrap_or_default();
+        let timeout = match &options.timeout {
+            Some(timeout) => humantime::parse_duration(timeout).map_err(|e| {
+                AlixtError::Config(format!(
+                    "Invalid sse timeout '{timeout}' for request '{request}': {e}"
+                ))
+            })?,
+            None => Self::DEFAULT_TIMEOUT,
+        };
+        if options.max_events == Some(0) {
+            return Err(AlixtError::Config(format!(
+                "The sse max_events of request '{request}' has to be at least 1"
+            )));
+        }
+        let until_data = options
+            .until_data
+            .map(|pattern| {
+                Regex::new(&pattern).map_err(|e| {
+                    AlixtError::Config(format!(
+                        "Invalid sse until_data '{pattern}' for request '{request}': {e}"
+                    ))
+                })
+            })
+            .transpose()?;
+        Ok(Some(Self {
+            max_events: options.max_events,
+            until_event: options.until_event,
+            until_data,
+            timeout,
+        }))
+    }
+}
+
+/// A GraphQL operation, with its query file already read
+#[derive(Clone)]
+pub struct GraphQlOperation {
+    pub query: String,
+    pub variables: Option<HashMap<String, Value>>,
+    pub operation_name: Option<String>,
+}
+
+impl GraphQlOperation {
+    fn from_config(graphql: GraphQl, working_dir: &Path, request: &str) -> Result<Self, AlixtError> {
+        let query = match (graphql.query, graphql.query_file) {
+            (Some(query), None) => query,
+            (None, Some(path)) => {
+                let context = format!("graphql query_file of request '{request}'");
+                String::from_utf8(load_body_file(&path, working_dir, &context)?).map_err(|_| {
+                    AlixtError::Config(format!("The {context} is not UTF-8 text"))
+                })?
             }
-            if request.body.is_none() {
-                request.body = run.body.clone();
+            _ => {
+                return Err(AlixtError::Config(format!(
+                    "The graphql body of request '{request}' needs either a query or a query_file"
+                )));
             }
+        };
+        Ok(Self {
+            query,
+            variables: graphql.variables,
+            operation_name: graphql.operation_name,
+        })
+    }
+}
 
-            run_plan.requests.push(ExecuteRequest::from_request(request)?);
+/// Moves `jsonrpc_result` and `jsonrpc_error_code` into `subset_matches`, under the `result` and
+/// `error` members of the response
+fn jsonrpc_assertions(mut assert: Assert, rpc: Option<&JsonRpc>, request: &str) -> Result<Assert, AlixtError> {
+    if assert.jsonrpc_result.is_none() && assert.jsonrpc_error_code.is_none() {
+        return Ok(assert);
+    }
+    match rpc {
+        Some(JsonRpc::Call(call)) if call.notification != Some(true) => {}
+        Some(JsonRpc::Call(_)) => {
+            return Err(AlixtError::Config(format!(
+                "Request '{request}' sends a JSON-RPC notification, which gets no result or error to assert on"
+            )));
+        }
+        Some(JsonRpc::Batch(_)) => {
+            return Err(AlixtError::Config(format!(
+                "Request '{request}' sends a JSON-RPC batch, use subset_matches with pointers like \"/0/result\" instead of jsonrpc_result and jsonrpc_error_code"
+            )));
+        }
+        None => {
+            return Err(AlixtError::Config(format!(
+                "Request '{request}' asserts on a JSON-RPC response but has no jsonrpc body"
+            )));
         }
+    }
+    let subset = assert.subset_matches.get_or_insert_default();
+    for (pointer, value) in assert.jsonrpc_result.take().into_iter().flatten() {
+        subset.insert(format!("/result{pointer}"), value);
+    }
+    if let Some(code) = assert.jsonrpc_error_code.take() {
+        subset.insert("/error/code".to_string(), Value::from(code));
+    }
+    Ok(assert)
+}
 
-        Ok(run_plan)
+/// A part of a multipart body, with its file already read
+#[derive(Clone)]
+pub struct FormPart {
+    pub name: String,
+    pub content: PartContent,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+}
+
+#[derive(Clone)]
+pub enum PartContent {
+    // substituted like `body`
+    Text(String),
+    File(Vec<u8>),
+}
+
+impl FormPart {
+    fn from_config(part: MultipartPart, working_dir: &Path, request: &str) -> Result<Self, AlixtError> {
+        let (content, filename) = match (part.value, part.file) {
+            (Some(value), None) => (PartContent::Text(value), part.filename),
+            (None, Some(file)) => {
+                let filename = part.filename.or_else(|| {
+                    file.file_name().map(|name| name.to_string_lossy().into_owned())
+                });
+                let context = format!("multipart part '{}' of request '{request}'", part.name);
+                let bytes = load_body_file(&file, working_dir, &context)?;
+                (PartContent::File(bytes), filename)
+            }
+            _ => {
+                return Err(AlixtError::Config(format!(
+                    "Multipart part '{}' of request '{request}' needs either a value or a file",
+                    part.name
+                )));
+            }
+        };
+        Ok(Self {
+            name: part.name,
+            content,
+            filename,
+            content_type: part.content_type,
+        })
     }
 }
 
+/// Reads a file sent in a request body, resolved against the config directory like `data_file`
+fn load_body_file(path: &Path, working_dir: &Path, context: &str) -> Result<Vec<u8>, AlixtError> {
+    let full_path = working_dir.join(path);
+    if !full_path.exists() {
+        return Err(AlixtError::Config(format!(
+            "File not found for {context}: {:?}\n(Looked in {:?})",
+            path, full_path
+        )));
+    }
+    std::fs::read(&full_path).map_err(|e| {
+        AlixtError::Config(format!("Could not read {:?} for {context}: {e}", full_path))
+    })
+}
+
+/// Reads the rows of an inline `data` table or a `data_file`, resolved against the config
+/// directory like `env_file`
+fn load_rows(
+    data: Option<Vec<HashMap<String, Value>>>,
+    data_file: Option<PathBuf>,
+    working_dir: &Path,
+    context: &str,
+) -> Result<Option<Vec<Row>>, AlixtError> {
+    match (data, data_file) {
+        (Some(_), Some(_)) => Err(AlixtError::Config(format!(
+            "Both data and data_file are set for {context}, only one can be used"
+        ))),
+        (Some(data), None) => Ok(Some(data.into_iter().map(data::row_from_values).collect())),
+        (None, Some(path)) => {
+            let full_path = working_dir.join(&path);
+            if !full_path.exists() {
+                return Err(AlixtError::Config(format!(
+                    "Data file not found for {context}: {:?}\n(Looked in {:?})",
+                    path, full_path
+                )));
+            }
+            Ok(Some(data::load_data_file(&full_path)?))
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+#[derive(Clone, Default)]
 pub struct ExecuteRequest {
     pub name: String,
     pub url: String,
     pub method: Method,
     pub body: Option<String>,
+    // a body_file with template = false, sent as it is
+    pub body_bytes: Option<Vec<u8>>,
+    // sorted by name, so the encoded body does not change between invocations
+    pub form: Option<Vec<(String, String)>>,
+    pub multipart: Option<Vec<FormPart>>,
+    pub graphql: Option<GraphQlOperation>,
+    pub jsonrpc: Option<JsonRpc>,
+    // from body_file, form, graphql or jsonrpc, only used when the headers have no Content-Type
+    pub content_type: Option<String>,
+    // set for `stream = "sse"`, the response is read as events instead of a whole body
+    pub sse: Option<SseStream>,
+    pub raw: Raw,
+    pub vars: Option<IndexMap<String, String>>,
+    pub skip_if: Option<String>,
+    pub only_if: Option<String>,
+    pub row: Option<Row>,
+    pub foreach: Option<String>,
+    // resolved against the config directory
+    pub unix_socket: Option<PathBuf>,
+    // how many redirects are followed, 0 returns the redirect response itself
+    pub redirect_limit: usize,
 
     pub headers: Option<HashMap<String, String>>,
     pub capture: Option<HashMap<String, String>>,
+    pub export: Vec<String>,
     pub assert: Option<Assert>
 }
 
 impl ExecuteRequest {
-    fn from_request(request: Request) -> Result<ExecuteRequest, AlixtError> {
+    fn from_request(request: Request, working_dir: &Path) -> Result<ExecuteRequest, AlixtError> {
         let Some(host) = request.host else {
             return Err(AlixtError::Config(format!("Internal Error: Request {} missing host, got past checks", request.name)));
         };
@@ -185,27 +896,202 @@ impl ExecuteRequest {
             return Err(AlixtError::Config(format!("Internal Error: Request {} missing method, got past checks", request.name)));
         };
 
-        let method = Self::_convert_method(method);
+        let method = Self::_convert_method(method)?;
+
+        let export = request.export.unwrap_or_default();
+        for key in &export {
+            if !request.capture.as_ref().is_some_and(|capture| capture.contains_key(key)) {
+                return Err(AlixtError::Config(format!(
+                    "Request '{}' exports '{key}', which it does not capture",
+                    request.name
+                )));
+            }
+        }
+
+        let mut body = request.body;
+        let mut body_bytes = None;
+        let mut content_type = None;
+        if let Some(body_file) = request.body_file {
+            let context = format!("body_file of request '{}'", request.name);
+            let bytes = load_body_file(body_file.path(), working_dir, &context)?;
+            if body_file.template() {
+                body = Some(String::from_utf8(bytes).map_err(|_| {
+                    AlixtError::Config(format!(
+                        "Body file {:?} of request '{}' is not UTF-8 text, set template = false to send it verbatim",
+                        body_file.path(),
+                        request.name
+                    ))
+                })?);
+            } else {
+                body_bytes = Some(bytes);
+            }
+            content_type = body_file.content_type().map(String::from);
+        }
+        let form = request.form.map(|form| {
+            content_type = Some("application/x-www-form-urlencoded".to_string());
+            let mut fields: Vec<(String, String)> = form.into_iter().collect();
+            fields.sort();
+            fields
+        });
+        let multipart = request
+            .multipart
+            .map(|parts| {
+                parts
+                    .into_iter()
+                    .map(|part| FormPart::from_config(part, working_dir, &request.name))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()?;
+        let graphql = request
+            .graphql
+            .map(|graphql| GraphQlOperation::from_config(graphql, working_dir, &request.name))
+            .transpose()?;
+        if graphql.is_some() || request.jsonrpc.is_some() {
+            content_type = Some("application/json".to_string());
+        }
+        let assert = request
+            .assert
+            .map(|assert| jsonrpc_assertions(assert, request.jsonrpc.as_ref(), &request.name))
+            .transpose()?;
+
+        let sse = SseStream::from_config(request.stream, request.sse, assert.as_ref(), &request.name)?;
 
         let request_plan = ExecuteRequest {
             name: request.name,
             url: Self::_format_url(request.scheme.unwrap_or(Scheme::Http), host, request.port, request.path),
             method,
-            body: request.body,
+            body,
+            body_bytes,
+            form,
+            multipart,
+            graphql,
+            jsonrpc: request.jsonrpc,
+            content_type,
+            sse,
+            raw: request.raw.unwrap_or_default(),
+            vars: request.vars,
+            skip_if: request.skip_if,
+            only_if: request.only_if,
+            row: None,
+            foreach: request.foreach,
+            unix_socket: request.unix_socket,
+            redirect_limit: request
+                .follow_redirects
+                .map_or(FollowRedirects::DEFAULT_MAX, FollowRedirects::limit),
             headers: request.headers,
             capture: request.capture,
-            assert: request.assert,
+            export,
+            assert,
         };
+        request_plan.validate_templates()?;
+        request_plan.validate_head()?;
         Ok(request_plan)
     }
-    fn _convert_method(method: ConfigMethod) -> Method {
-        match method {
+
+    /// One copy of the request per data row, with the row index added to the name
+    fn expand(self, rows: Vec<Row>) -> impl Iterator<Item = ExecuteRequest> {
+        rows.into_iter().enumerate().map(move |(index, row)| {
+            let mut request = self.clone();
+            request.name = format!("{} [row {index}]", self.name);
+            // request level rows are the innermost scope, so they win over the run's row
+            let mut merged = request.row.take().unwrap_or_default();
+            merged.extend(row);
+            request.row = Some(merged);
+            request
+        })
+    }
+
+    /// Catches unknown template functions and filters before anything is sent
+    fn validate_templates(&self) -> Result<(), AlixtError> {
+        let context = format!("request '{}'", self.name);
+        check_templates([&self.url], &context)?;
+        if !self.raw.body {
+            check_templates(&self.body, &context)?;
+            check_templates(self.form.iter().flatten().map(|(_, value)| value), &context)?;
+            for part in self.multipart.iter().flatten() {
+                if let PartContent::Text(text) = &part.content {
+                    check_templates([text], &context)?;
+                }
+                check_templates(&part.filename, &context)?;
+            }
+            if let Some(graphql) = &self.graphql {
+                check_templates([&graphql.query].into_iter().chain(&graphql.operation_name), &context)?;
+                check_templates(
+                    graphql.variables.iter().flat_map(|variables| variables.values()).flat_map(json::strings),
+                    &context,
+                )?;
+            }
+            for call in self.jsonrpc.iter().flat_map(JsonRpc::calls) {
+                check_templates([&call.method], &context)?;
+                check_templates(call.params.iter().flat_map(json::strings), &context)?;
+            }
+        }
+        if let Some(headers) = &self.headers
+            && !self.raw.headers
+        {
+            check_templates(headers.values(), &context)?;
+        }
+        if let Some(vars) = &self.vars {
+            check_templates(vars.values(), &context)?;
+        }
+        check_templates(self.skip_if.iter().chain(&self.only_if), &context)?;
+        if let Some(assert) = &self.assert
+            && !self.raw.assert
+        {
+            for map in [&assert.body_matches, &assert.subset_matches, &assert.subset_regex]
+                .into_iter()
+                .flatten()
+            {
+                check_templates(map.values().filter_map(|v| v.as_str()), &context)?;
+            }
+            check_templates(&assert.graphql_error_matches, &context)?;
+        }
+        Ok(())
+    }
+    /// A HEAD response has no body, so nothing can be captured from or asserted on it
+    fn validate_head(&self) -> Result<(), AlixtError> {
+        if self.method != Method::HEAD {
+            return Ok(());
+        }
+        if self.capture.is_some() || self.sse.is_some() {
+            return Err(AlixtError::Config(format!(
+                "Request '{}' is a HEAD request and has no body to capture from or stream",
+                self.name
+            )));
+        }
+        if let Some(assert) = &self.assert
+            && (assert.body_matches.is_some()
+                || assert.subset_matches.is_some()
+                || assert.subset_includes.is_some()
+                || assert.subset_regex.is_some()
+                || assert.graphql_no_errors == Some(true)
+                || assert.graphql_error_matches.is_some()
+                || assert.jsonrpc_result.is_some()
+                || assert.jsonrpc_error_code.is_some()
+                || assert.sse_event_count.is_some()
+                || assert.sse_events.is_some())
+        {
+            return Err(AlixtError::Config(format!(
+                "Request '{}' is a HEAD request and has no body to assert on, only status, cookies, redirects and tls can be checked",
+                self.name
+            )));
+        }
+        Ok(())
+    }
+    fn _convert_method(method: ConfigMethod) -> Result<Method, AlixtError> {
+        Ok(match method {
             ConfigMethod::Get => Method::GET,
             ConfigMethod::Put => Method::PUT,
             ConfigMethod::Post => Method::POST,
             ConfigMethod::Patch => Method::PATCH,
             ConfigMethod::Delete => Method::DELETE,
-        }
+            ConfigMethod::Head => Method::HEAD,
+            ConfigMethod::Options => Method::OPTIONS,
+            ConfigMethod::Trace => Method::TRACE,
+            ConfigMethod::Connect => Method::CONNECT,
+            ConfigMethod::Extension(name) => Method::from_bytes(name.to_ascii_uppercase().as_bytes())
+                .map_err(|_| AlixtError::Config(format!("Invalid HTTP method '{name}'")))?,
+        })
     }
 
     fn _format_url(scheme: Scheme, host: String, port: Option<u16>, path: Option<String>) -> String {
@@ -222,6 +1108,17 @@ impl ExecuteRequest {
     }
 }
 
+fn check_templates(
+    texts: impl IntoIterator<Item = impl AsRef<str>>,
+    context: &str,
+) -> Result<(), AlixtError> {
+    for text in texts {
+        expression::validate(text.as_ref())
+            .map_err(|e| AlixtError::Config(format!("Invalid template in {context}: {e}")))?;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -266,4 +1163,407 @@ mod tests {
         assert_eq!(req_override.url, "https://api.example.com:80/a/new/path");
         assert_eq!(req_override.method, reqwest::Method::POST);
     }
+
+    #[test]
+    fn test_vars_resolve_in_declaration_order() {
+        // enough keys that a hash map would visit them out of order on most invocations
+        let toml_input = r#"
+        [vars]
+        z = "z"
+        a = "{{z}}-a"
+        y = "{{a}}-y"
+        b = "{{y}}-b"
+        x = "{{b}}-x"
+
+        [[run]]
+        name = "Vars"
+        method = "Get"
+        scheme = "Http"
+        host = "localhost"
+        [run.vars]
+        r2 = "r2"
+        r1 = "{{r2}}-{{x}}"
+        r0 = "{{run.r1}}-r0"
+
+        [[run.request]]
+        name = "Request vars"
+        [run.request.vars]
+        q9 = "{{r0}}"
+        q1 = "{{q9}}-q1"
+        "#;
+        let config: Config = toml::from_str(toml_input).unwrap();
+        let plan = TestPlan::from_config(config, Path::new(".")).unwrap();
+
+        let mut global = crate::models::context::Global::new();
+        global.add_variables(plan.vars.as_ref().unwrap());
+        assert_eq!(global.variables["x"], "z-a-y-b-x");
+
+        let mut state = crate::models::context::RunState::new(std::sync::Arc::new(global));
+        state.add_run_variables(plan.runs[0].vars.as_ref().unwrap());
+        assert_eq!(state.run_variables["r0"], "r2-z-a-y-b-x-r0");
+        state.add_request_variables(plan.runs[0].requests[0].vars.as_ref().unwrap());
+        assert_eq!(state.request_variables["q1"], "r2-z-a-y-b-x-r0-q1");
+    }
+
+    #[test]
+    fn test_tls_files() {
+        let dir = std::env::temp_dir().join(format!("alixt-tls-files-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("certs")).unwrap();
+        for file in ["ca.pem", "client.pem", "client.key", "other-ca.pem", "run.pem"] {
+            std::fs::write(dir.join("certs").join(file), "").unwrap();
+        }
+        let plan = |toml_input: &str| {
+            let config: Config = toml::from_str(toml_input).expect("valid config");
+            TestPlan::from_config(config, &dir)
+        };
+        let runs = r#"
+        [[run]]
+        name = "Inherits"
+        method = "Get"
+        scheme = "Https"
+        host = "localhost"
+        [[run.request]]
+        name = "request"
+        [[run]]
+        name = "Overrides"
+        method = "Get"
+        scheme = "Https"
+        host = "localhost"
+        ca_cert = "certs/other-ca.pem"
+        client_cert = "certs/run.pem"
+        [[run.request]]
+        name = "request"
+        "#;
+
+        let root = r#"
+        ca_cert = "certs/ca.pem"
+        client_cert = "certs/client.pem"
+        client_key = "certs/client.key"
+        "#;
+        let tested = plan(&format!("{root}{runs}")).expect("valid plan");
+        let inherited = &tested.runs[0].client.tls;
+        assert_eq!(inherited.ca_cert, Some(dir.join("certs/ca.pem")));
+        assert_eq!(inherited.client_cert, Some(dir.join("certs/client.pem")));
+        assert_eq!(inherited.client_key, Some(dir.join("certs/client.key")));
+        // a client_cert replaces the inherited pair, its key is not borrowed from the root
+        let overridden = &tested.runs[1].client.tls;
+        assert_eq!(overridden.ca_cert, Some(dir.join("certs/other-ca.pem")));
+        assert_eq!(overridden.client_cert, Some(dir.join("certs/run.pem")));
+        assert_eq!(overridden.client_key, None);
+
+        let Err(AlixtError::Config(e)) = plan(&format!("client_key = \"certs/client.key\"\n{runs}")) else {
+            panic!("a client_key without a client_cert is an error");
+        };
+        assert_eq!(e, "client_key is set without a client_cert in the config root");
+
+        let Err(AlixtError::Config(e)) = plan(&runs.replace("certs/other-ca.pem", "certs/missing.pem")) else {
+            panic!("a missing PEM file is an error");
+        };
+        assert!(e.starts_with("ca_cert file not found: \"certs/missing.pem\""), "{e}");
+        assert!(e.ends_with("in run 'Overrides'"), "{e}");
+    }
+
+    #[test]
+    fn test_data_rows_expand_requests() {
+        let toml_input = r#"
+        [[run]]
+        name = "Rows"
+        method = "Get"
+        scheme = "Http"
+        host = "0.0.0.0"
+        data = [{ user = "alice" }, { user = "bob" }]
+
+        [[run.request]]
+        name = "Lookup"
+        path = "/users/{{row.user}}/{{row.id}}"
+        data = [{ id = 1 }, { id = 2, user = "carol" }]
+        "#;
+        let config: Config = toml::from_str(toml_input).expect("valid config");
+        let plan = TestPlan::from_config(config, Path::new(".")).expect("valid plan");
+
+        let requests = &plan.runs[0].requests;
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[1].name, "Lookup [run row 0] [row 1]");
+        let row = requests[1].row.as_ref().expect("row");
+        assert_eq!(row.get("user").map(String::as_str), Some("carol"));
+        assert_eq!(row.get("id").map(String::as_str), Some("2"));
+        assert_eq!(
+            requests[2].row.as_ref().and_then(|r| r.get("user")).map(String::as_str),
+            Some("bob")
+        );
+    }
+
+    #[test]
+    fn test_run_dependencies() {
+        let runs = |a: &str, b: &str| {
+            format!(
+                r#"
+                [[run]]
+                name = "A"
+                depends_on = [{a}]
+                request = []
+
+                [[run]]
+                name = "B"
+                depends_on = [{b}]
+                request = []
+                "#
+            )
+        };
+        let plan_for = |toml_input: String| {
+            let config: Config = toml::from_str(&toml_input).expect("valid config");
+            TestPlan::from_config(config, Path::new("."))
+        };
+
+        let plan = plan_for(runs("", "\"A\"")).expect("valid plan");
+        assert_eq!(plan.runs[1].depends_on, vec![0]);
+        assert!(plan_for(runs("\"B\"", "\"A\"")).is_err());
+        assert!(plan_for(runs("\"A\"", "")).is_err());
+        assert!(plan_for(runs("\"C\"", "")).is_err());
+    }
+
+    #[test]
+    fn test_global_export_sources() {
+        let toml_input = r#"
+        [[run]]
+        name = "First"
+        depends_on = ["Second"]
+        method = "Get"
+        scheme = "Http"
+        host = "0.0.0.0"
+        [[run.request]]
+        name = "export"
+        capture = { id = "/id" }
+        export = ["id"]
+
+        [[run]]
+        name = "Second"
+        method = "Get"
+        scheme = "Http"
+        host = "0.0.0.0"
+        [[run.request]]
+        name = "export"
+        capture = { token = "/token" }
+        export = ["token"]
+
+        [[run]]
+        name = "Third"
+        request = []
+        "#;
+        let config: Config = toml::from_str(toml_input).expect("valid config");
+        let plan = TestPlan::from_config(config, Path::new(".")).expect("valid plan");
+
+        // First waits on Second, so Second can not see what First exports
+        assert_eq!(plan.runs[0].global_sources, vec![1]);
+        assert!(plan.runs[1].global_sources.is_empty());
+        assert_eq!(plan.runs[2].global_sources, vec![0, 1]);
+    }
+
+    #[test]
+    fn test_resolve_overrides() {
+        let toml_input = r#"
+        resolve = { "api.example.com:443" = "10.0.0.5", "Cdn.Example.com" = "10.0.0.9" }
+
+        [[run]]
+        name = "Node B"
+        resolve = { "api.example.com" = "[::1]" }
+        request = []
+
+        [[run]]
+        name = "Default"
+        request = []
+        "#;
+        let config: Config = toml::from_str(toml_input).expect("valid config");
+        let mut plan = TestPlan::from_config(config, Path::new(".")).expect("valid plan");
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+
+        assert_eq!(plan.runs[0].client.resolve["api.example.com"], ip("::1"));
+        assert_eq!(plan.runs[0].client.resolve["cdn.example.com"], ip("10.0.0.9"));
+        assert_eq!(plan.runs[1].client, plan.client);
+
+        plan.override_resolve(&[("api.example.com".to_string(), ip("127.0.0.1"))]);
+        assert_eq!(plan.runs[0].client.resolve["api.example.com"], ip("127.0.0.1"));
+        assert_eq!(plan.client.resolve["api.example.com"], ip("127.0.0.1"));
+
+        let conflicting = r#"
+        resolve = { "api.example.com:443" = "10.0.0.5", "api.example.com:80" = "10.0.0.6" }
+        run = []
+        "#;
+        let config: Config = toml::from_str(conflicting).expect("valid config");
+        assert!(TestPlan::from_config(config, Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn test_unix_socket_defaults() {
+        let toml_input = r#"
+        [[run]]
+        name = "Daemon"
+        method = "Get"
+        unix_socket = "/run/app.sock"
+        [[run.request]]
+        name = "info"
+        path = "/v1/info"
+        [[run.request]]
+        name = "other daemon"
+        unix_socket = "sockets/other.sock"
+        "#;
+        let config: Config = toml::from_str(toml_input).expect("valid config");
+        let plan = TestPlan::from_config(config, Path::new("/etc/alixt")).expect("valid plan");
+        let requests = &plan.runs[0].requests;
+
+        assert_eq!(requests[0].url, "http://localhost/v1/info");
+        assert_eq!(requests[0].unix_socket, Some(PathBuf::from("/run/app.sock")));
+        assert_eq!(requests[1].unix_socket, Some(PathBuf::from("/etc/alixt/sockets/other.sock")));
+    }
+
+    #[test]
+    fn test_methods() {
+        let toml_input = r#"
+        [[run]]
+        name = "Methods"
+        scheme = "Http"
+        host = "0.0.0.0"
+        request = [
+            { name = "head", method = "HEAD", assert = { status = 200 } },
+            { name = "options", method = "Options" },
+            { name = "purge", method = "purge" },
+            { name = "propfind", method = "PROPFIND" },
+        ]
+        "#;
+        let config: Config = toml::from_str(toml_input).expect("valid config");
+        let plan = TestPlan::from_config(config, Path::new(".")).expect("valid plan");
+        let methods: Vec<_> = plan.runs[0].requests.iter().map(|r| r.method.as_str()).collect();
+        assert_eq!(methods, ["HEAD", "OPTIONS", "PURGE", "PROPFIND"]);
+
+        for invalid in [
+            r#"{ name = "head", method = "Head", assert = { subset_matches = { "/id" = 1 } } }"#,
+            r#"{ name = "head", method = "Head", capture = { id = "/id" } }"#,
+            r#"{ name = "space", method = "NOT A METHOD" }"#,
+        ] {
+            let toml_input = format!(
+                "[[run]]\nname = \"Invalid\"\nscheme = \"Http\"\nhost = \"0.0.0.0\"\nrequest = [{invalid}]"
+            );
+            let config: Config = toml::from_str(&toml_input).expect("valid config");
+            assert!(TestPlan::from_config(config, Path::new(".")).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_body_files() {
+        let dir = std::env::temp_dir().join(format!("alixt-body-files-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("fixtures")).unwrap();
+        std::fs::write(dir.join("fixtures/order.json"), r#"{"id": "{{id}}"}"#).unwrap();
+        std::fs::write(dir.join("fixtures/logo.png"), [0x89, b'P', b'N', b'G', 0xff]).unwrap();
+
+        let toml_input = r#"
+        [[run]]
+        name = "Uploads"
+        method = "Post"
+        scheme = "Http"
+        host = "0.0.0.0"
+        body_file = "fixtures/order.json"
+        [[run.request]]
+        name = "order"
+        [[run.request]]
+        name = "logo"
+        body_file = { path = "fixtures/logo.png", template = false, content_type = "image/png" }
+        "#;
+        let config: Config = toml::from_str(toml_input).expect("valid config");
+        let plan = TestPlan::from_config(config, &dir).expect("valid plan");
+        let requests = &plan.runs[0].requests;
+        assert_eq!(requests[0].body.as_deref(), Some(r#"{"id": "{{id}}"}"#));
+        assert_eq!(requests[1].body, None);
+        assert_eq!(requests[1].body_bytes.as_deref(), Some(&[0x89, b'P', b'N', b'G', 0xff][..]));
+        assert_eq!(requests[1].content_type.as_deref(), Some("image/png"));
+
+        for invalid in [
+            r#"body_file = "fixtures/missing.json""#,
+            r#"body_file = "fixtures/logo.png""#,
+            "body_file = \"fixtures/order.json\"\nbody = \"{}\"",
+        ] {
+            let toml_input = format!(
+                "[[run]]\nname = \"Invalid\"\nmethod = \"Post\"\nscheme = \"Http\"\nhost = \"0.0.0.0\"\n[[run.request]]\nname = \"upload\"\n{invalid}"
+            );
+            let config: Config = toml::from_str(&toml_input).expect("valid config");
+            assert!(TestPlan::from_config(config, &dir).is_err(), "{invalid}");
+        }
+
+        // a run's defaults conflict just like a request's own bodies, even for requests with a
+        // body of their own
+        let toml_input = r#"
+        [[run]]
+        name = "Conflicting"
+        method = "Post"
+        scheme = "Http"
+        host = "0.0.0.0"
+        body = "{}"
+        body_file = "fixtures/order.json"
+        [[run.request]]
+        name = "upload"
+        body = "[]"
+        "#;
+        let config: Config = toml::from_str(toml_input).expect("valid config");
+        let Err(AlixtError::Config(e)) = TestPlan::from_config(config, &dir) else {
+            panic!("a run with body and body_file is an error");
+        };
+        assert_eq!(e, "Run 'Conflicting' has body and body_file, only one kind of body can be used");
+        let mut config: Config = toml::from_str(toml_input).expect("valid config");
+        let run = config.run.pop().unwrap();
+        let mut request: Request = toml::from_str(r#"name = "inherits""#).unwrap();
+        let Err(AlixtError::Config(e)) = apply_run_defaults(&run, &mut request) else {
+            panic!("inheriting body and body_file is an error");
+        };
+        assert_eq!(
+            e,
+            "Request 'inherits', through run 'Conflicting', has body and body_file, only one kind of body can be used"
+        );
+
+        let toml_input = r#"
+        [[run]]
+        name = "Forms"
+        method = "Post"
+        scheme = "Http"
+        host = "0.0.0.0"
+        body = "inherited by requests without a body of their own"
+        [[run.request]]
+        name = "login"
+        form = { username = "ada", password = "{{password}}" }
+        [[run.request]]
+        name = "upload"
+        multipart = [
+            { name = "title", value = "Logo" },
+            { name = "file", file = "fixtures/logo.png", content_type = "image/png" },
+        ]
+        "#;
+        let config: Config = toml::from_str(toml_input).expect("valid config");
+        let plan = TestPlan::from_config(config, &dir).expect("valid plan");
+        let requests = &plan.runs[0].requests;
+        assert_eq!(requests[0].body, None);
+        assert_eq!(
+            requests[0].form.as_deref(),
+            Some(&[
+                ("password".to_string(), "{{password}}".to_string()),
+                ("username".to_string(), "ada".to_string()),
+            ][..])
+        );
+        assert_eq!(requests[0].content_type.as_deref(), Some("application/x-www-form-urlencoded"));
+        let parts = requests[1].multipart.as_ref().expect("multipart parts");
+        assert_eq!(parts[1].filename.as_deref(), Some("logo.png"));
+        assert!(matches!(&parts[1].content, PartContent::File(bytes) if bytes.len() == 5));
+
+        let conflicting = r#"
+        [[run]]
+        name = "Invalid"
+        method = "Post"
+        scheme = "Http"
+        host = "0.0.0.0"
+        [[run.request]]
+        name = "both"
+        body = "{}"
+        form = { a = "b" }
+        "#;
+        let config: Config = toml::from_str(conflicting).expect("valid config");
+        assert!(TestPlan::from_config(config, &dir).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
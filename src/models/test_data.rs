@@ -15,20 +15,30 @@
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
 
-use std::time::Duration;
+use std::{net::SocketAddr, time::Duration};
 
 use serde::{Serialize, Serializer};
 
 #[derive(Default, Debug, Serialize)]
 pub struct TestData {
+    // the requests of the [capture] section, which run before anything else
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture: Option<RunData>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub setup: Vec<RunData>,
     #[serde(rename = "runs")]
     pub run_data: Vec<RunData>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub teardown: Vec<RunData>,
 }
 
 impl TestData {
     pub fn new() -> Self {
         Self {
+            capture: None,
+            setup: Vec::new(),
             run_data: Vec::new(),
+            teardown: Vec::new(),
         }
     }
 }
@@ -36,17 +46,38 @@ impl TestData {
 #[derive(Debug, Serialize)]
 pub struct RunData {
     pub name: String,
+    // reason the whole run was skipped by its skip_if / only_if condition
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<String>,
     #[serde(rename = "requests")]
     pub outcomes: Vec<RequestOutcome>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub teardown: Vec<RequestOutcome>,
 }
 
 impl RunData {
     pub fn new(name: String) -> Self {
         Self {
             name,
+            skipped: None,
             outcomes: Vec::new(),
+            teardown: Vec::new(),
         }
     }
+    pub fn has_failures(&self) -> bool {
+        self.outcomes
+            .iter()
+            .chain(&self.teardown)
+            .any(|outcome| outcome.passing.is_failed())
+    }
+    /// Requests skipped by their skip_if / only_if condition, foreach iterations included
+    pub fn skipped_requests(&self) -> usize {
+        fn count(outcome: &RequestOutcome) -> usize {
+            usize::from(outcome.passing.is_skipped())
+                + outcome.iterations.iter().map(count).sum::<usize>()
+        }
+        self.outcomes.iter().map(count).sum()
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -62,6 +93,75 @@ pub struct RequestOutcome {
     pub response_body: Option<String>,
     #[serde(serialize_with = "serialize_duration_as_seconds", rename = "duration_seconds")]
     pub duration: Duration,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub generated_values: Vec<GeneratedValue>,
+    // one outcome per element when the request has a `foreach`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub iterations: Vec<RequestOutcome>,
+    // the captured values came from the capture cache, nothing was sent
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub cached: bool,
+    // every redirect that was followed, in order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub redirects: Vec<RedirectHop>,
+    // where the redirects ended, only set when there were any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub final_url: Option<String>,
+    // address the final response came from, the proxy's when one is used
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_addr: Option<SocketAddr>,
+    // what the server presented on the request's own connection, for https requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<TlsSummary>,
+    // received from a `stream = "sse"` response, in order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub events: Option<Vec<SseEvent>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RedirectHop {
+    pub status: u16,
+    // the URL that answered with the redirect
+    pub url: String,
+    pub location: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TlsSummary {
+    // e.g. "TLSv1.3", reqwest does not expose the protocol or the cipher suite, so they are only
+    // known when a `min_tls_version` assertion made a handshake of its own
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub protocol: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cipher_suite: Option<String>,
+    // leaf certificate first, as the server sent it
+    pub chain: Vec<CertificateSummary>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CertificateSummary {
+    pub subject: String,
+    pub issuer: String,
+    pub not_after: String,
+    pub expires_in_days: i64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub subject_alt_names: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SseEvent {
+    // "message" when the server did not name the event
+    pub event: String,
+    pub data: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+/// A value produced by a non deterministic template function such as `uuid()`
+#[derive(Debug, Clone, Serialize)]
+pub struct GeneratedValue {
+    pub expression: String,
+    pub value: String,
 }
 
 
@@ -73,20 +173,28 @@ fn serialize_duration_as_seconds<S: Serializer>(duration: &Duration, serializer:
 pub enum AssertionOutcome {
     Passed,
     Failed(Vec<FailureType>),
+    Skipped { reason: String },
 }
 impl AssertionOutcome {
     pub fn push(&mut self, failure: FailureType) {
         match self {
-            Self::Passed => *self = Self::Failed(vec![failure]),
+            Self::Passed | Self::Skipped { .. } => *self = Self::Failed(vec![failure]),
             Self::Failed(fails) => fails.push(failure),
         }
     }
     pub fn is_passing(&self) -> bool {
         match self {
             Self::Passed => true,
-            Self::Failed(_) => false,
+            Self::Failed(_) | Self::Skipped { .. } => false,
         }
     }
+    // a skipped request neither passed nor failed, it never breaks a run or fails the suite
+    pub fn is_failed(&self) -> bool {
+        matches!(self, Self::Failed(_))
+    }
+    pub fn is_skipped(&self) -> bool {
+        matches!(self, Self::Skipped { .. })
+    }
     pub fn take(&mut self) -> Self {
         match self {
             Self::Passed => {
@@ -95,6 +203,9 @@ impl AssertionOutcome {
             Self::Failed(_) => {
                 std::mem::replace(self, AssertionOutcome::Failed(Vec::new()))
             },
+            Self::Skipped { .. } => {
+                std::mem::replace(self, AssertionOutcome::Skipped { reason: String::new() })
+            },
         }
     }
 }
@@ -107,4 +218,17 @@ pub enum FailureType {
     JsonValueMismatch { path: String, expected: String, found: String },
     JsonRegexMismatch { path: String, pattern: String, found: String },
     JsonNotString { path: String },
+    ForeachNotArray { foreach: String, found: String },
+    IterationsFailed { failed: usize, total: usize },
+    CaptureMissing { variable: String, pattern: String },
+    CookieMissing { name: String },
+    CookieMismatch { name: String, attribute: String, expected: String, found: String },
+    RedirectCountMismatch { expected: usize, found: usize },
+    FinalUrlMismatch { expected: String, found: String },
+    LocationMismatch { expected: String, found: Option<String> },
+    TlsMismatch { check: String, expected: String, found: String },
+    GraphqlErrors { messages: Vec<String> },
+    GraphqlErrorMismatch { pattern: String, messages: Vec<String> },
+    JsonRpcIdMismatch { expected: String, found: String },
+    SseMismatch { check: String, expected: String, found: String },
 }
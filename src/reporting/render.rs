@@ -14,13 +14,13 @@
 // You should have received a copy of the GNU General Public License
 // along with this program. If not, see <http://www.gnu.org/licenses/>.
 
-use colored::Colorize;
+use colored::{ColoredString, Colorize};
 use serde_json::Value;
 
 use crate::{
     models::{
         error::AlixtError,
-        test_data::{AssertionOutcome, FailureType, TestData},
+        test_data::{AssertionOutcome, FailureType, RequestOutcome, RunData, TestData},
     },
     reporting::table::{
         BOTTOM_LEFT, BOTTOM_RIGHT, HORIZONTAL, TOP_LEFT, TOP_RIGHT, Table, VERTICAL,
@@ -32,28 +32,107 @@ pub fn generate_text<W: std::io::Write>(
     outcome: TestData,
 ) -> Result<(), AlixtError> {
     writeln!(writer, "[TEST RESULTS]")?;
+    if let Some(run) = outcome.capture {
+        write_run_text(writer, run, "CAPTURE")?;
+    }
+    for run in outcome.setup {
+        write_run_text(writer, run, "SETUP")?;
+    }
     for run in outcome.run_data {
-        writeln!(writer, "\n[RUN]: '{}'", run.name)?;
-        for req in run.outcomes {
+        write_run_text(writer, run, "RUN")?;
+    }
+    for run in outcome.teardown {
+        write_run_text(writer, run, "TEARDOWN")?;
+    }
+    Ok(())
+}
+
+fn write_run_text<W: std::io::Write>(
+    writer: &mut W,
+    run: RunData,
+    label: &str,
+) -> Result<(), AlixtError> {
+    writeln!(writer, "\n[{label}]: '{}'", run.name)?;
+    if let Some(reason) = run.skipped {
+        writeln!(writer, "Skipped: {reason}")?;
+        return Ok(());
+    }
+    for req in run.outcomes {
+        write_request_text(writer, req)?;
+    }
+    if !run.teardown.is_empty() {
+        writeln!(writer, "\n[RUN TEARDOWN]: '{}'", run.name)?;
+        for req in run.teardown {
+            write_request_text(writer, req)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_request_text<W: std::io::Write>(
+    writer: &mut W,
+    req: RequestOutcome,
+) -> Result<(), AlixtError> {
+    if let AssertionOutcome::Skipped { reason } = &req.passing {
+        writeln!(writer, "\n[REQUEST]: '{}',\nSkipped: {},", req.name, reason)?;
+        return Ok(());
+    }
+    writeln!(
+        writer,
+        "\n[REQUEST]: '{}',\nTarget: '{}',\nPassed: {},\nBreaking: {},\nDuration: {} seconds,",
+        req.name,
+        req.url,
+        req.passing.is_passing(),
+        req.breaking,
+        req.duration.as_secs_f64(),
+    )?;
+    if req.cached {
+        writeln!(writer, "Cached: true,")?;
+    }
+    for hop in &req.redirects {
+        writeln!(writer, "Redirect: {} {} -> {}", hop.status, hop.url, hop.location)?;
+    }
+    if let Some(final_url) = &req.final_url {
+        writeln!(writer, "Final URL: '{}',", final_url)?;
+    }
+    if let Some(remote_addr) = &req.remote_addr {
+        writeln!(writer, "Connected: {},", remote_addr)?;
+    }
+    if let Some(tls) = &req.tls {
+        if let (Some(protocol), Some(cipher_suite)) = (&tls.protocol, &tls.cipher_suite) {
+            writeln!(writer, "TLS: {}, {},", protocol, cipher_suite)?;
+        }
+        for cert in &tls.chain {
             writeln!(
                 writer,
-                "\n[REQUEST]: '{}',\nTarget: '{}',\nPassed: {},\nBreaking: {},\nDuration: {} seconds,",
-                req.name,
-                req.url,
-                req.passing.is_passing(),
-                req.breaking,
-                req.duration.as_secs_f64(),
+                "Certificate: '{}' issued by '{}', expires {} ({} days),",
+                cert.subject, cert.issuer, cert.not_after, cert.expires_in_days
             )?;
-            if let Some(body) = req.response_body {
-                let body = if let Ok(json) = serde_json::from_str::<Value>(&body) {
-                    serde_json::to_string_pretty(&json).unwrap_or(body.clone())
-                } else {
-                    body
-                };
-                writeln!(writer, "Body = ```\n{}```", body)?;
-            } else {
-                writeln!(writer)?;
-            }
+        }
+    }
+    if let Some(events) = &req.events {
+        writeln!(writer, "Events: {},", events.len())?;
+        for event in events {
+            writeln!(writer, "Event: {} = {}", event.event, event.data)?;
+        }
+    }
+    for generated in &req.generated_values {
+        writeln!(writer, "Generated: {} = {}", generated.expression, generated.value)?;
+    }
+    if let Some(body) = req.response_body {
+        let body = if let Ok(json) = serde_json::from_str::<Value>(&body) {
+            serde_json::to_string_pretty(&json).unwrap_or(body.clone())
+        } else {
+            body
+        };
+        writeln!(writer, "Body = ```\n{}```", body)?;
+    } else {
+        writeln!(writer)?;
+    }
+    if !req.iterations.is_empty() {
+        writeln!(writer, "Iterations: {}", req.iterations.len())?;
+        for iteration in req.iterations {
+            write_request_text(writer, iteration)?;
         }
     }
     Ok(())
@@ -64,6 +143,110 @@ struct TableData {
     assertions: Vec<(String, AssertionOutcome)>,
 }
 
+/// Adds a row for the request, and an indented row for each of its foreach iterations. Returns
+/// whether the request failed.
+fn push_request_row(
+    table: &mut TableData,
+    request: &mut RequestOutcome,
+    indent: &str,
+) -> Result<bool, AlixtError> {
+    if let AssertionOutcome::Skipped { reason } = &request.passing {
+        table.table.push_row([
+            "".white(),
+            "SKIP".yellow(),
+            format!("{indent}{} ({reason})", request.name).yellow(),
+            "".white(),
+            "".white(),
+        ])?;
+        return Ok(false);
+    }
+    let failed = request.passing.is_failed();
+    let passed = if failed {
+        table
+            .assertions
+            .push((request.name.clone(), request.passing.take()));
+        "FAIL".red()
+    } else {
+        "PASS".green()
+    };
+    let breaking = if request.breaking && failed {
+        "BREAK".red()
+    } else {
+        "".white()
+    };
+    let status = if let Some(status) = request.status {
+        format!("{status}").yellow()
+    } else {
+        "".white()
+    };
+    let cached = if request.cached { " (cached)" } else { "" };
+    table.table.push_row([
+        breaking,
+        passed,
+        format!("{indent}{}{cached}", request.name).yellow(),
+        status,
+        format!("{}s", request.duration.as_secs_f32()).yellow(),
+    ])?;
+    for iteration in &mut request.iterations {
+        push_request_row(table, iteration, "  └ ")?;
+    }
+    Ok(failed)
+}
+
+enum RunResult {
+    Passed,
+    Failed,
+    Skipped,
+}
+
+fn run_table(mut run: RunData, title: ColoredString) -> Result<(TableData, RunResult), AlixtError> {
+    let mut table = TableData {
+        table: Table::<5>::new()
+            .title(title)
+            .headers([
+                "".white(),
+                "Result".blue(),
+                "Name".blue(),
+                "Code".blue(),
+                "Duration".blue(),
+            ])
+            .collect()?,
+        assertions: Vec::new(),
+    };
+    if let Some(reason) = &run.skipped {
+        table.table.push_row([
+            "".white(),
+            "SKIP".yellow(),
+            reason.yellow(),
+            "".white(),
+            "".white(),
+        ])?;
+        return Ok((table, RunResult::Skipped));
+    }
+    let mut failed = false;
+    for request in &mut run.outcomes {
+        failed |= push_request_row(&mut table, request, "")?;
+    }
+    if !run.teardown.is_empty() {
+        table.table.push_row([
+            "".white(),
+            "".white(),
+            "teardown".blue(),
+            "".white(),
+            "".white(),
+        ])?;
+        for request in &mut run.teardown {
+            push_request_row(&mut table, request, "")?;
+        }
+    }
+    let result = if failed {
+        RunResult::Failed
+    } else {
+        RunResult::Passed
+    };
+    Ok((table, result))
+}
+
 pub fn generate_table<W: std::io::Write>(
     writer: &mut W,
     outcome: TestData,
@@ -72,65 +255,55 @@ pub fn generate_table<W: std::io::Write>(
     let mut tables: Vec<TableData> = vec![];
     let mut passing: u16 = 0;
     let mut failing: u16 = 0;
-    for mut run in outcome.run_data {
-        let mut failed = false;
-        let mut table = TableData {
-            table: Table::<5>::new()
-                .title(run.name.blue())
-                .headers([
-                    "".white(),
-                    "Result".blue(),
-                    "Name".blue(),
-                    "Code".blue(),
-                    "Duration".blue(),
-                ])
-                .collect()?,
-            assertions: Vec::new(),
-        };
-        for request in &mut run.outcomes {
-            let passed = if request.passing.is_passing() {
-                "PASS".green()
-            } else {
-                table
-                    .assertions
-                    .push((request.name.clone(), request.passing.take()));
-                failed = true;
-                "FAIL".red()
-            };
-            let breaking = if request.breaking && !request.passing.is_passing() {
-                "BREAK".red()
-            } else {
-                "".white()
-            };
-            let status = if let Some(status) = request.status {
-                format!("{status}").yellow()
-            } else {
-                "".white()
-            };
-            table.table.push_row([
-                breaking,
-                passed,
-                request.name.yellow(),
-                status,
-                format!("{}s", request.duration.as_secs_f32()).yellow(),
-            ])?;
-        }
-        if failed {
-            failing += 1;
-        } else {
-            passing += 1;
+    let mut skipped: u16 = 0;
+    let mut skipped_requests: usize = 0;
+    // setup, teardown runs and per run teardown requests are not counted as runs, but any
+    // failure there still fails the suite
+    let mut hooks_failed = false;
+    if let Some(run) = outcome.capture {
+        hooks_failed |= run.has_failures();
+        tables.push(run_table(run, "Capture".blue())?.0);
+    }
+    for run in outcome.setup {
+        hooks_failed |= run.has_failures();
+        let title = format!("Setup: {}", run.name).blue();
+        tables.push(run_table(run, title)?.0);
+    }
+    for run in outcome.run_data {
+        hooks_failed |= run
+            .teardown
+            .iter()
+            .any(|outcome| outcome.passing.is_failed());
+        skipped_requests += run.skipped_requests();
+        let title = run.name.blue();
+        let (table, result) = run_table(run, title)?;
+        match result {
+            RunResult::Passed => passing += 1,
+            RunResult::Failed => failing += 1,
+            RunResult::Skipped => skipped += 1,
         }
         tables.push(table);
     }
+    for run in outcome.teardown {
+        hooks_failed |= run.has_failures();
+        let title = format!("Teardown: {}", run.name).blue();
+        tables.push(run_table(run, title)?.0);
+    }
     let passing_text = format!("{passing}").green();
     let failing_text = format!("{failing}").red();
+    let skipped_text = format!("{skipped}").yellow();
+    let skipped_requests_text = format!("{skipped_requests}").yellow();
 
     let message = vec![
         "All runs finished. ".blue(),
         passing_text,
         " passing, ".blue(),
         failing_text,
-        " failing.".blue(),
+        " failing, ".blue(),
+        skipped_text,
+        " skipped, ".blue(),
+        skipped_requests_text,
+        " requests skipped.".blue(),
     ];
     let message_len: usize = message.iter().fold(0, |acc, w| acc + w.len());
     writeln!(
@@ -224,13 +397,109 @@ pub fn generate_table<W: std::io::Write>(
                             "Not A String".red(),
                         ])?;
                     },
+                    FailureType::ForeachNotArray { foreach, found } => {
+                        request_table.push_row([
+                            "ForeachNotArray".blue(),
+                            format!("{} = <Array>", foreach).green(),
+                            found.red(),
+                        ])?;
+                    },
+                    FailureType::IterationsFailed { failed, total } => {
+                        request_table.push_row([
+                            "IterationsFailed".blue(),
+                            format!("0 of {} failed", total).green(),
+                            format!("{} of {} failed", failed, total).red(),
+                        ])?;
+                    },
+                    FailureType::CaptureMissing { variable, pattern } => {
+                        request_table.push_row([
+                            "CaptureMissing".blue(),
+                            format!("{} = {}", variable, pattern).green(),
+                            "Not Found".red(),
+                        ])?;
+                    },
+                    FailureType::CookieMissing { name } => {
+                        request_table.push_row([
+                            "CookieMissing".blue(),
+                            format!("Set-Cookie: {}", name).green(),
+                            "Not Set".red(),
+                        ])?;
+                    },
+                    FailureType::CookieMismatch { name, attribute, expected, found } => {
+                        request_table.push_row([
+                            "CookieMismatch".blue(),
+                            format!("{} {} = {}", name, attribute, expected).green(),
+                            found.red(),
+                        ])?;
+                    },
+                    FailureType::RedirectCountMismatch { expected, found } => {
+                        request_table.push_row([
+                            "RedirectCountMismatch".blue(),
+                            format!("{}", expected).green(),
+                            format!("{}", found).red(),
+                        ])?;
+                    },
+                    FailureType::FinalUrlMismatch { expected, found } => {
+                        request_table.push_row([
+                            "FinalUrlMismatch".blue(),
+                            expected.green(),
+                            found.red(),
+                        ])?;
+                    },
+                    FailureType::LocationMismatch { expected, found } => {
+                        request_table.push_row([
+                            "LocationMismatch".blue(),
+                            expected.green(),
+                            found.unwrap_or("<none>".to_string()).red(),
+                        ])?;
+                    },
+                    FailureType::GraphqlErrors { messages } => {
+                        request_table.push_row([
+                            "GraphqlErrors".blue(),
+                            "no errors".green(),
+                            messages.join("; ").red(),
+                        ])?;
+                    },
+                    FailureType::GraphqlErrorMismatch { pattern, messages } => {
+                        let found = if messages.is_empty() {
+                            "no errors".to_string()
+                        } else {
+                            messages.join("; ")
+                        };
+                        request_table.push_row([
+                            "GraphqlErrorMismatch".blue(),
+                            pattern.green(),
+                            found.red(),
+                        ])?;
+                    },
+                    FailureType::JsonRpcIdMismatch { expected, found } => {
+                        request_table.push_row([
+                            "JsonRpcIdMismatch".blue(),
+                            format!("id {}", expected).green(),
+                            found.red(),
+                        ])?;
+                    },
+                    FailureType::TlsMismatch { check, expected, found } => {
+                        request_table.push_row([
+                            "TlsMismatch".blue(),
+                            format!("{} {}", check, expected).green(),
+                            found.red(),
+                        ])?;
+                    },
+                    FailureType::SseMismatch { check, expected, found } => {
+                        request_table.push_row([
+                            "SseMismatch".blue(),
+                            format!("{} {}", check, expected).green(),
+                            found.red(),
+                        ])?;
+                    },
                 }
             }
             request_table.render(writer)?;
         }
     }
 
-    if failing == 0 {
+    if failing == 0 && !hooks_failed {
         writeln!(
             writer,
             "\n{}{}{}",
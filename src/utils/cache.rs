@@ -0,0 +1,232 @@
+// This file is part of alixt.
+// Copyright (C) 2025 Devon Harley Offutt
+//
+// alixt is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Stores the values captured by `[[capture.request]]` entries with a `cache` table between
+//! invocations.
+//!
+//! Entries are keyed by a hash of the request definition and of the values of the variables it
+//! references, so a different target or different credentials never reuse another login, while
+//! unrelated variables, like one built from `uuid()`, do not invalidate the entry. The file holds
+//! secrets, on unix it is only readable by the owner.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    models::{error::AlixtError, plan::ExecuteRequest},
+    utils::expression::{self, Scope},
+};
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    // unix seconds
+    expires_at: u64,
+    values: HashMap<String, String>,
+}
+
+pub struct CaptureCache {
+    path: PathBuf,
+    entries: HashMap<String, CacheEntry>,
+    changed: bool,
+    refresh: bool,
+}
+
+impl CaptureCache {
+    /// `$XDG_CACHE_HOME/alixt/captures.json`, falling back to `~/.cache/alixt/captures.json`
+    pub fn default_path() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+        Some(base.join("alixt").join("captures.json"))
+    }
+
+    /// Reads the cache file, a missing or unreadable file is an empty cache. With `refresh` every
+    /// lookup misses, but new values are still stored.
+    pub fn load(path: PathBuf, refresh: bool) -> Self {
+        let entries = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self {
+            path,
+            entries,
+            changed: false,
+            refresh,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&HashMap<String, String>> {
+        if self.refresh {
+            return None;
+        }
+        let entry = self.entries.get(key)?;
+        (entry.expires_at > unix_now()).then_some(&entry.values)
+    }
+
+    pub fn insert(&mut self, key: String, values: HashMap<String, String>, ttl: Duration) {
+        let expires_at = unix_now().saturating_add(ttl.as_secs());
+        self.entries.insert(key, CacheEntry { expires_at, values });
+        self.changed = true;
+    }
+
+    /// Writes the cache back if anything was added, dropping expired entries on the way
+    pub fn save(mut self) -> Result<(), AlixtError> {
+        if !self.changed {
+            return Ok(());
+        }
+        let now = unix_now();
+        self.entries.retain(|_, entry| entry.expires_at > now);
+
+        if let Some(dir) = self.path.parent() {
+            create_private_dir(dir)?;
+        }
+        let content = serde_json::to_string(&self.entries)?;
+        let mut file = open_private_file(&self.path)?;
+        file.write_all(content.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Hash of everything that decides what a capture request sends, `scope` is what its placeholders
+/// are resolved in
+pub fn cache_key(request: &ExecuteRequest, scope: &impl Scope) -> String {
+    let mut hasher = Sha256::new();
+    let mut field = |name: &str, value: &str| {
+        hasher.update(name.as_bytes());
+        hasher.update([0]);
+        hasher.update(value.len().to_le_bytes());
+        hasher.update(value.as_bytes());
+    };
+
+    field("method", request.method.as_str());
+    field("url", &request.url);
+    field("body", request.body.as_deref().unwrap_or_default());
+    for (key, value) in sorted(request.headers.as_ref()) {
+        field("header", key);
+        field("header value", value);
+    }
+    for (variable, pattern) in sorted(request.capture.as_ref()) {
+        field("capture", variable);
+        field("capture pattern", pattern);
+    }
+    let texts = [&request.url]
+        .into_iter()
+        .chain(&request.body)
+        .chain(request.headers.iter().flat_map(|headers| headers.values()));
+    let variables: BTreeSet<String> = texts.flat_map(|text| expression::variables(text)).collect();
+    for variable in &variables {
+        field("variable", variable);
+        field("variable value", &scope.lookup(variable).unwrap_or_default());
+    }
+
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+fn sorted(map: Option<&HashMap<String, String>>) -> BTreeMap<&String, &String> {
+    map.into_iter().flatten().collect()
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> Result<(), AlixtError> {
+    use std::os::unix::fs::DirBuilderExt;
+    fs::DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(dir: &Path) -> Result<(), AlixtError> {
+    fs::create_dir_all(dir)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn open_private_file(path: &Path) -> Result<fs::File, AlixtError> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // mode only applies to new files, tighten one that already existed
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn open_private_file(path: &Path) -> Result<fs::File, AlixtError> {
+    Ok(fs::File::create(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::models::context::{Global, RunState};
+
+    #[test]
+    fn test_cache_key_and_expiry() {
+        let request = ExecuteRequest {
+            name: "login".to_string(),
+            url: "https://{{host}}/login".to_string(),
+            method: reqwest::Method::POST,
+            body: Some(r#"{"password": "{{env.PASSWORD}}"}"#.to_string()),
+            ..Default::default()
+        };
+        let state = |password: &str| {
+            let mut global = Global::seeded(0);
+            global.env_variables.insert("PASSWORD".to_string(), password.to_string());
+            global.variables.insert("run_id".to_string(), "5f0c-uuid".to_string());
+            RunState::new(Arc::new(global))
+        };
+        let mut one = state("one");
+        let key = cache_key(&request, &one);
+        assert_ne!(key, cache_key(&request, &state("two")));
+        // only the variables the request references are part of the key
+        one.run_variables.insert("unused".to_string(), "x".to_string());
+        assert_eq!(key, cache_key(&request, &one));
+        one.run_variables.insert("host".to_string(), "staging.example.com".to_string());
+        assert_ne!(key, cache_key(&request, &one));
+
+        let values = HashMap::from([("token".to_string(), "abc".to_string())]);
+        let mut cache = CaptureCache::load(PathBuf::from("/nonexistent/captures.json"), false);
+        cache.insert(key.clone(), values.clone(), Duration::from_secs(60));
+        assert_eq!(cache.get(&key), Some(&values));
+        cache.insert(key.clone(), values, Duration::ZERO);
+        assert_eq!(cache.get(&key), None);
+    }
+}
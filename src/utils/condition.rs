@@ -0,0 +1,140 @@
+// This file is part of alixt.
+// Copyright (C) 2025 Devon Harley Offutt
+//
+// alixt is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Evaluates `skip_if` / `only_if` conditions.
+//!
+//! A condition is one or more comparisons joined with `&&` and `||` (`&&` binds tighter), where a
+//! comparison is `a == b`, `a != b`, `!a` or a lone value. Operands may be quoted with `'` or `"`.
+//! A lone value is true unless it is empty, `false`, `0`, `no`, `off`, or a placeholder that could
+//! not be resolved.
+//!
+//! The condition is split into its operands before their placeholders are substituted, so a value
+//! containing quotes or operators cannot change the structure of the expression.
+
+pub fn evaluate(condition: &str, mut substitute: impl FnMut(&str) -> String) -> bool {
+    split_outside_quotes(condition, "||")
+        .into_iter()
+        .any(|any| {
+            split_outside_quotes(any, "&&")
+                .into_iter()
+                .all(|text| comparison(text, &mut substitute))
+        })
+}
+
+fn comparison(text: &str, substitute: &mut impl FnMut(&str) -> String) -> bool {
+    if let [left, right] = split_outside_quotes(text, "!=")[..] {
+        return substitute(operand(left)) != substitute(operand(right));
+    }
+    if let [left, right] = split_outside_quotes(text, "==")[..] {
+        return substitute(operand(left)) == substitute(operand(right));
+    }
+    let text = text.trim();
+    if let Some(negated) = text.strip_prefix('!') {
+        return !truthy(negated, substitute);
+    }
+    truthy(text, substitute)
+}
+
+fn truthy(text: &str, substitute: &mut impl FnMut(&str) -> String) -> bool {
+    let value = substitute(operand(text));
+    if value.trim_start().starts_with("{{") {
+        return false;
+    }
+    !matches!(
+        value.to_lowercase().as_str(),
+        "" | "false" | "0" | "no" | "off"
+    )
+}
+
+fn operand(text: &str) -> &str {
+    let text = text.trim();
+    if text.len() >= 2
+        && ((text.starts_with('\'') && text.ends_with('\''))
+            || (text.starts_with('"') && text.ends_with('"')))
+    {
+        &text[1..text.len() - 1]
+    } else {
+        text
+    }
+}
+
+// placeholders are skipped like quoted text, their filters and arguments are not operators
+fn split_outside_quotes<'a>(text: &'a str, separator: &str) -> Vec<&'a str> {
+    let mut parts = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut in_placeholder = false;
+    let mut last = 0;
+    let mut skip_until = 0;
+    for (index, c) in text.char_indices() {
+        if index < skip_until {
+            continue;
+        }
+        let rest = &text[index..];
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if in_placeholder && rest.starts_with("}}") => {
+                in_placeholder = false;
+                skip_until = index + 2;
+            }
+            None if in_placeholder => {}
+            None if rest.starts_with("{{") => {
+                in_placeholder = true;
+                skip_until = index + 2;
+            }
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if rest.starts_with(separator) => {
+                parts.push(&text[last..index]);
+                last = index + separator.len();
+                skip_until = last;
+            }
+            None => {}
+        }
+    }
+    parts.push(&text[last..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conditions() {
+        let literal = |text: &str| text.to_string();
+        assert!(evaluate("on == 'on'", literal));
+        assert!(!evaluate("off == \"on\"", literal));
+        assert!(evaluate("staging != 'prod' && 1", literal));
+        assert!(evaluate("false || 'a||b' == 'a||b'", literal));
+        assert!(!evaluate("'a||b' == 'a'", literal));
+        assert!(!evaluate("{{env.FEATURE_X}}", literal));
+        assert!(evaluate("!off", literal));
+    }
+
+    #[test]
+    fn test_substituted_values_are_operands() {
+        let substitute = |text: &str| {
+            text.replace("{{name}}", "O'Brien")
+                .replace("{{ops}}", "x' == 'x' || 'a")
+                .replace("{{ missing | default('a || b') }}", "a || b")
+        };
+        assert!(evaluate("'{{name}}' == \"O'Brien\"", substitute));
+        assert!(!evaluate("'{{name}}' == 'x'", substitute));
+        assert!(!evaluate("'{{ops}}' == 'x'", substitute));
+        assert!(evaluate("{{ops}} != x", substitute));
+        assert!(evaluate("{{ missing | default('a || b') }} == 'a || b'", substitute));
+    }
+}
@@ -0,0 +1,220 @@
+// This file is part of alixt.
+// Copyright (C) 2025 Devon Harley Offutt
+//
+// alixt is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Cookie jars for runs with `cookies = true`, and the `cookies` assertions on Set-Cookie headers.
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use cookie::{Cookie, Expiration};
+use reqwest::{
+    Url,
+    cookie::{CookieStore, Jar},
+    header::{COOKIE, HeaderMap, HeaderValue, SET_COOKIE},
+};
+
+use crate::models::{
+    config::{CookieAssert, SameSite},
+    test_data::{AssertionOutcome, FailureType},
+};
+
+/// The cookie jar of a single run, nothing in it is shared with other runs
+pub struct RunCookies {
+    jar: Jar,
+    seeds: HashMap<String, String>,
+    seeded_hosts: HashSet<String>,
+}
+
+impl RunCookies {
+    pub fn new(seeds: HashMap<String, String>) -> Self {
+        Self {
+            jar: Jar::default(),
+            seeds,
+            seeded_hosts: HashSet::new(),
+        }
+    }
+
+    /// Adds the cookies the jar holds for `url` to the request headers. The seed cookies are put
+    /// in the jar the first time a host is seen, so a response can still replace them.
+    pub fn apply(&mut self, url: &Url, headers: &mut HeaderMap) {
+        if let Some(host) = url.host_str()
+            && self.seeded_hosts.insert(host.to_string())
+        {
+            for (name, value) in &self.seeds {
+                self.jar.add_cookie_str(&format!("{name}={value}; Path=/"), url);
+            }
+        }
+        let Some(cookies) = self.jar.cookies(url) else {
+            return;
+        };
+        // a Cookie header written in the config is kept, the jar's cookies are appended to it
+        let combined = match headers.get(COOKIE).map(HeaderValue::to_str) {
+            Some(Ok(existing)) => cookies
+                .to_str()
+                .ok()
+                .and_then(|jar| HeaderValue::from_str(&format!("{existing}; {jar}")).ok()),
+            _ => Some(cookies),
+        };
+        if let Some(value) = combined {
+            headers.insert(COOKIE, value);
+        }
+    }
+
+    /// Stores the cookies a response set
+    pub fn store(&self, url: &Url, headers: &HeaderMap) {
+        self.jar
+            .set_cookies(&mut headers.get_all(SET_COOKIE).iter(), url);
+    }
+}
+
+/// The raw Set-Cookie header values of a response
+pub fn set_cookie_headers(headers: &HeaderMap) -> Vec<String> {
+    headers
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .map(String::from)
+        .collect()
+}
+
+/// Checks the cookies set by a response against the `cookies` assertions. `value` has already been
+/// substituted by the caller.
+pub fn check_cookies(
+    set_cookies: &[String],
+    expected: &HashMap<String, CookieAssert>,
+    outcome: &mut AssertionOutcome,
+) {
+    let parsed: Vec<Cookie> = set_cookies
+        .iter()
+        .filter_map(|header| Cookie::parse(header.as_str()).ok())
+        .collect();
+
+    for (name, assert) in expected {
+        // when a cookie is set twice the last one wins, as in a browser
+        let cookie = parsed.iter().rev().find(|cookie| cookie.name() == name);
+        let mut mismatch = |attribute: &str, expected: String, found: String| {
+            if expected != found {
+                outcome.push(FailureType::CookieMismatch {
+                    name: name.clone(),
+                    attribute: attribute.to_string(),
+                    expected,
+                    found,
+                });
+            }
+        };
+
+        let Some(cookie) = cookie else {
+            if assert.present != Some(false) {
+                outcome.push(FailureType::CookieMissing { name: name.clone() });
+            }
+            continue;
+        };
+        if assert.present == Some(false) {
+            mismatch("present", "false".to_string(), "true".to_string());
+            continue;
+        }
+
+        if let Some(value) = &assert.value {
+            mismatch("value", value.clone(), cookie.value().to_string());
+        }
+        if let Some(secure) = assert.secure {
+            let found = cookie.secure().unwrap_or(false);
+            mismatch("Secure", secure.to_string(), found.to_string());
+        }
+        if let Some(http_only) = assert.http_only {
+            let found = cookie.http_only().unwrap_or(false);
+            mismatch("HttpOnly", http_only.to_string(), found.to_string());
+        }
+        if let Some(same_site) = assert.same_site {
+            let found = match cookie.same_site() {
+                Some(cookie::SameSite::Strict) => SameSite::Strict.to_string(),
+                Some(cookie::SameSite::Lax) => SameSite::Lax.to_string(),
+                Some(cookie::SameSite::None) => SameSite::None.to_string(),
+                None => "<unset>".to_string(),
+            };
+            mismatch("SameSite", same_site.to_string(), found);
+        }
+
+        let expires_in = expires_in_seconds(cookie);
+        if let Some(session) = assert.session {
+            mismatch("session", session.to_string(), expires_in.is_none().to_string());
+        }
+        if assert.expires_in_min.is_none() && assert.expires_in_max.is_none() {
+            continue;
+        }
+        let Some(seconds) = expires_in else {
+            mismatch("expires in", "an expiry".to_string(), "session cookie".to_string());
+            continue;
+        };
+        if let Some(min) = assert.expires_in_min
+            && seconds < min
+        {
+            mismatch("expires in", format!(">= {min}s"), format!("{seconds}s"));
+        }
+        if let Some(max) = assert.expires_in_max
+            && seconds > max
+        {
+            mismatch("expires in", format!("<= {max}s"), format!("{seconds}s"));
+        }
+    }
+}
+
+/// Seconds until the cookie expires, Max-Age taking precedence over Expires. None for a session
+/// cookie.
+fn expires_in_seconds(cookie: &Cookie) -> Option<i64> {
+    if let Some(max_age) = cookie.max_age() {
+        return Some(max_age.whole_seconds());
+    }
+    let Some(Expiration::DateTime(expires)) = cookie.expires() else {
+        return None;
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or_default();
+    Some(expires.unix_timestamp() - now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cookie_assertions() {
+        let set_cookies = vec![
+            "session=abc; Secure; HttpOnly; SameSite=Lax; Max-Age=3600".to_string(),
+            "theme=dark".to_string(),
+        ];
+        let assert = |toml_input: &str| {
+            let expected: HashMap<String, CookieAssert> =
+                toml::from_str(toml_input).expect("valid assertion");
+            let mut outcome = AssertionOutcome::Passed;
+            check_cookies(&set_cookies, &expected, &mut outcome);
+            outcome.is_passing()
+        };
+
+        assert!(assert(
+            r#"session = { value = "abc", secure = true, http_only = true, same_site = "lax", expires_in_min = 3000 }"#
+        ));
+        assert!(assert(r#"theme = { session = true, secure = false }"#));
+        assert!(assert(r#"tracking = { present = false }"#));
+        assert!(!assert(r#"session = { same_site = "strict" }"#));
+        assert!(!assert(r#"theme = { expires_in_min = 1 }"#));
+        assert!(!assert(r#"missing = {}"#));
+    }
+}
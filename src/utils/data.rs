@@ -0,0 +1,149 @@
+// This file is part of alixt.
+// Copyright (C) 2025 Devon Harley Offutt
+//
+// alixt is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::{collections::HashMap, path::Path};
+
+use serde_json::Value;
+
+use crate::models::error::AlixtError;
+
+pub type Row = HashMap<String, String>;
+
+/// Loads the rows of a `data_file`, a CSV file with a header line, or a JSON array of objects
+pub fn load_data_file(path: &Path) -> Result<Vec<Row>, AlixtError> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => load_csv(path),
+        Some("json") => {
+            let content = std::fs::read_to_string(path).map_err(|e| {
+                AlixtError::Config(format!("Failed to read data file {:?}: {e}", path))
+            })?;
+            let json = serde_json::from_str::<Value>(&content).map_err(|e| {
+                AlixtError::Config(format!("Failed to read data file {:?}: {e}", path))
+            })?;
+            let Value::Array(items) = json else {
+                return Err(AlixtError::Config(format!(
+                    "Data file {:?} must contain a JSON array of objects",
+                    path
+                )));
+            };
+            items
+                .into_iter()
+                .map(|item| match item {
+                    Value::Object(map) => Ok(map.into_iter().collect::<HashMap<_, _>>()),
+                    _ => Err(AlixtError::Config(format!(
+                        "Data file {:?} must contain a JSON array of objects",
+                        path
+                    ))),
+                })
+                .map(|row| row.map(row_from_values))
+                .collect()
+        }
+        _ => Err(AlixtError::Config(format!(
+            "Unsupported data file {:?}, expected a .csv or .json file",
+            path
+        ))),
+    }
+}
+
+fn load_csv(path: &Path) -> Result<Vec<Row>, AlixtError> {
+    let mut reader = csv::Reader::from_path(path)
+        .map_err(|e| AlixtError::Config(format!("Failed to read data file {:?}: {e}", path)))?;
+    let headers = reader
+        .headers()
+        .map_err(|e| AlixtError::Config(format!("Failed to read data file {:?}: {e}", path)))?
+        .clone();
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record
+            .map_err(|e| AlixtError::Config(format!("Failed to read data file {:?}: {e}", path)))?;
+        rows.push(
+            headers
+                .iter()
+                .zip(record.iter())
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        );
+    }
+    Ok(rows)
+}
+
+/// Turns an inline `data` table into a row, strings are used as is, everything else as JSON
+pub fn row_from_values(values: HashMap<String, Value>) -> Row {
+    values
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(s) => s,
+                v => v.to_string(),
+            };
+            (key, value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_data_file() {
+        let dir = std::env::temp_dir().join(format!("alixt-data-files-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, content: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, content).unwrap();
+            path
+        };
+        let value = |row: &Row, key: &str| row.get(key).cloned();
+
+        let rows = load_data_file(&write("users.csv", "user,id\nalice,1\nbob,2\n")).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(value(&rows[1], "user").as_deref(), Some("bob"));
+        assert_eq!(value(&rows[1], "id").as_deref(), Some("2"));
+
+        let rows = load_data_file(&write(
+            "users.json",
+            r#"[{ "user": "alice", "id": 1 }, { "user": "bob", "tags": ["a"] }]"#,
+        ))
+        .unwrap();
+        assert_eq!(value(&rows[0], "id").as_deref(), Some("1"));
+        assert_eq!(value(&rows[1], "tags").as_deref(), Some(r#"["a"]"#));
+
+        let path = write("mixed.json", r#"[{ "user": "alice" }, "bob"]"#);
+        let Err(AlixtError::Config(e)) = load_data_file(&path) else {
+            panic!("a JSON array with a non object is an error");
+        };
+        assert_eq!(e, format!("Data file {path:?} must contain a JSON array of objects"));
+
+        let path = write("broken.json", "[{");
+        let Err(AlixtError::Config(e)) = load_data_file(&path) else {
+            panic!("invalid JSON is an error");
+        };
+        assert!(e.starts_with(&format!("Failed to read data file {path:?}")), "{e}");
+        let path = dir.join("missing.json");
+        let Err(AlixtError::Config(e)) = load_data_file(&path) else {
+            panic!("a missing file is an error");
+        };
+        assert!(e.starts_with(&format!("Failed to read data file {path:?}")), "{e}");
+
+        let path = write("users.yaml", "- user: alice");
+        let Err(AlixtError::Config(e)) = load_data_file(&path) else {
+            panic!("only csv and json files are read");
+        };
+        assert_eq!(e, format!("Unsupported data file {path:?}, expected a .csv or .json file"));
+    }
+}
@@ -0,0 +1,440 @@
+// This file is part of alixt.
+// Copyright (C) 2025 Devon Harley Offutt
+//
+// alixt is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+//! Tokenizer and evaluator for `{{ ... }}` placeholders.
+//!
+//! A placeholder holds a single expression: a variable, a literal, or a function call, followed
+//! by any number of `| filter` or `| filter(args)` stages, e.g. `{{ token | default("anon") }}`.
+//! Placeholders that do not parse, or that reference a missing variable without a `default`, are
+//! left in the text untouched. A literal `{{` can be written as `\{{`.
+
+use rand::rngs::StdRng;
+
+use crate::utils::functions;
+
+/// Where an expression looks up variables and draws randomness from
+pub trait Scope {
+    fn lookup(&self, key: &str) -> Option<String>;
+    fn rng(&mut self) -> &mut StdRng;
+    /// Called with the placeholder and its value whenever a non deterministic function was used
+    fn record(&mut self, _expression: &str, _value: &str) {}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    LParen,
+    RParen,
+    Comma,
+    Pipe,
+}
+
+#[derive(Debug, PartialEq)]
+enum Term {
+    Literal(String),
+    Variable(String),
+    Call { name: String, args: Vec<Term> },
+}
+
+#[derive(Debug, PartialEq)]
+struct Filter {
+    name: String,
+    args: Vec<Term>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Expression {
+    head: Term,
+    filters: Vec<Filter>,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '-')
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' | ',' | '|' => {
+                chars.next();
+                tokens.push(match c {
+                    '(' => Token::LParen,
+                    ')' => Token::RParen,
+                    ',' => Token::Comma,
+                    _ => Token::Pipe,
+                });
+            }
+            '"' | '\'' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some(ch) if ch == c => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('t') => value.push('\t'),
+                            Some(escaped) => value.push(escaped),
+                            None => return Err("unterminated string literal".to_string()),
+                        },
+                        Some(ch) => value.push(ch),
+                        None => return Err("unterminated string literal".to_string()),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            c if is_word_char(c) => {
+                let mut word = String::new();
+                while let Some(&ch) = chars.peek() {
+                    if !is_word_char(ch) {
+                        break;
+                    }
+                    word.push(ch);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+            other => return Err(format!("unexpected character '{other}'")),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expression(&mut self) -> Result<Expression, String> {
+        let head = self.term()?;
+        let mut filters = Vec::new();
+        while self.peek() == Some(&Token::Pipe) {
+            self.next();
+            let Some(Token::Word(name)) = self.next() else {
+                return Err("expected a filter name after '|'".to_string());
+            };
+            let args = if self.peek() == Some(&Token::LParen) {
+                self.arguments()?
+            } else {
+                Vec::new()
+            };
+            filters.push(Filter { name, args });
+        }
+        if let Some(token) = self.peek() {
+            return Err(format!("unexpected token {token:?}"));
+        }
+        Ok(Expression { head, filters })
+    }
+
+    fn term(&mut self) -> Result<Term, String> {
+        match self.next() {
+            Some(Token::Str(value)) => Ok(Term::Literal(value)),
+            Some(Token::Word(word)) if word.parse::<i64>().is_ok() => Ok(Term::Literal(word)),
+            Some(Token::Word(word)) => {
+                if self.peek() == Some(&Token::LParen) {
+                    let args = self.arguments()?;
+                    Ok(Term::Call { name: word, args })
+                } else {
+                    Ok(Term::Variable(word))
+                }
+            }
+            Some(token) => Err(format!("unexpected token {token:?}")),
+            None => Err("empty expression".to_string()),
+        }
+    }
+
+    fn arguments(&mut self) -> Result<Vec<Term>, String> {
+        // consume '('
+        self.next();
+        let mut args = Vec::new();
+        if self.peek() == Some(&Token::RParen) {
+            self.next();
+            return Ok(args);
+        }
+        loop {
+            args.push(self.term()?);
+            match self.next() {
+                Some(Token::Comma) => continue,
+                Some(Token::RParen) => return Ok(args),
+                _ => return Err("expected ',' or ')' in argument list".to_string()),
+            }
+        }
+    }
+}
+
+fn parse(source: &str) -> Result<Expression, String> {
+    let tokens = tokenize(source)?;
+    Parser {
+        tokens,
+        position: 0,
+    }
+    .expression()
+}
+
+enum Segment<'a> {
+    Text(&'a str),
+    // the full `{{ ... }}` text, and the expression between the braces
+    Placeholder(&'a str, &'a str),
+}
+
+/// Splits text into literal chunks and placeholders. The closing `}}` of a placeholder is
+/// searched for outside of string literals, so `{{ x | default("}}") }}` works.
+fn split(input: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut rest = input;
+    while let Some(start) = rest.find("{{") {
+        if rest[..start].ends_with('\\') {
+            // `\{{` is an escaped literal `{{`, the backslash is dropped
+            segments.push(Segment::Text(&rest[..start - 1]));
+            segments.push(Segment::Text("{{"));
+            rest = &rest[start + 2..];
+            continue;
+        }
+        segments.push(Segment::Text(&rest[..start]));
+        let after = &rest[start + 2..];
+
+        let mut quote: Option<char> = None;
+        let mut escaped = false;
+        let mut end = None;
+        for (index, c) in after.char_indices() {
+            match quote {
+                Some(_) if escaped => escaped = false,
+                Some(_) if c == '\\' => escaped = true,
+                Some(q) if c == q => quote = None,
+                Some(_) => {}
+                None if c == '"' || c == '\'' => quote = Some(c),
+                None if after[index..].starts_with("}}") => {
+                    end = Some(index);
+                    break;
+                }
+                None => {}
+            }
+        }
+
+        // an unbalanced quote means this is not an expression anyway, fall back to the first `}}`
+        let Some(end) = end.or_else(|| after.find("}}")) else {
+            // no closing braces, everything from here on is plain text
+            segments.push(Segment::Text(&rest[start..]));
+            return segments;
+        };
+        segments.push(Segment::Placeholder(
+            &rest[start..start + end + 4],
+            &after[..end],
+        ));
+        rest = &after[end + 2..];
+    }
+    segments.push(Segment::Text(rest));
+    segments
+}
+
+/// Replaces every placeholder in `input` that can be evaluated in `scope`
+pub fn render(input: &str, scope: &mut impl Scope) -> String {
+    let mut output = String::with_capacity(input.len());
+    for segment in split(input) {
+        match segment {
+            Segment::Text(text) => output.push_str(text),
+            Segment::Placeholder(raw, body) => match evaluate(body.trim(), scope) {
+                Some(value) => output.push_str(&value),
+                None => output.push_str(raw),
+            },
+        }
+    }
+    output
+}
+
+fn evaluate(source: &str, scope: &mut impl Scope) -> Option<String> {
+    // keys that are not valid expressions, like `{{ my key }}`, still resolve as before
+    if let Some(value) = scope.lookup(source) {
+        return Some(value);
+    }
+    let expression = parse(source).ok()?;
+    let mut generated = false;
+
+    let mut value = eval_term(&expression.head, scope, &mut generated).ok()?;
+    for filter in &expression.filters {
+        if filter.name == "default" {
+            if value.is_none() {
+                value = eval_term(filter.args.first()?, scope, &mut generated).ok()?;
+            }
+            continue;
+        }
+        // a missing value passes through the remaining filters until a `default` picks it up
+        let Some(current) = value else {
+            continue;
+        };
+        let args = eval_args(&filter.args, scope, &mut generated).ok()??;
+        value = Some(functions::apply_filter(&filter.name, current, &args, scope.rng()).ok()?);
+    }
+
+    let value = value?;
+    if generated {
+        scope.record(source, &value);
+    }
+    Some(value)
+}
+
+fn eval_args(
+    args: &[Term],
+    scope: &mut impl Scope,
+    generated: &mut bool,
+) -> Result<Option<Vec<String>>, String> {
+    let mut values = Vec::with_capacity(args.len());
+    for arg in args {
+        let Some(value) = eval_term(arg, scope, generated)? else {
+            return Ok(None);
+        };
+        values.push(value);
+    }
+    Ok(Some(values))
+}
+
+fn eval_term(
+    term: &Term,
+    scope: &mut impl Scope,
+    generated: &mut bool,
+) -> Result<Option<String>, String> {
+    match term {
+        Term::Literal(value) => Ok(Some(value.clone())),
+        Term::Variable(key) => Ok(scope.lookup(key)),
+        Term::Call { name, args } => {
+            let Some(args) = eval_args(args, scope, generated)? else {
+                return Ok(None);
+            };
+            *generated |= functions::is_generator(name);
+            functions::call(name, &args, scope.rng()).map(Some)
+        }
+    }
+}
+
+/// The names of the variables the placeholders in `input` can look up, including the whole
+/// placeholder text, which is tried as a key before it is parsed
+pub fn variables(input: &str) -> Vec<String> {
+    fn collect(term: &Term, names: &mut Vec<String>) {
+        match term {
+            Term::Literal(_) => {}
+            Term::Variable(key) => names.push(key.clone()),
+            Term::Call { args, .. } => args.iter().for_each(|arg| collect(arg, names)),
+        }
+    }
+    let mut names = Vec::new();
+    for segment in split(input) {
+        let Segment::Placeholder(_, body) = segment else {
+            continue;
+        };
+        names.push(body.trim().to_string());
+        if let Ok(expression) = parse(body.trim()) {
+            collect(&expression.head, &mut names);
+            for filter in &expression.filters {
+                filter.args.iter().for_each(|arg| collect(arg, &mut names));
+            }
+        }
+    }
+    names
+}
+
+/// Checks every placeholder in `input` for unknown functions and filters, or calls with the wrong
+/// number of arguments. Placeholders that do not parse at all are treated as plain text.
+pub fn validate(input: &str) -> Result<(), String> {
+    for segment in split(input) {
+        let Segment::Placeholder(raw, body) = segment else {
+            continue;
+        };
+        let Ok(expression) = parse(body.trim()) else {
+            continue;
+        };
+        validate_expression(&expression).map_err(|e| format!("{e} in '{raw}'"))?;
+    }
+    Ok(())
+}
+
+fn validate_expression(expression: &Expression) -> Result<(), String> {
+    validate_term(&expression.head)?;
+    for filter in &expression.filters {
+        let Some(arity) = functions::filter_arity(&filter.name) else {
+            return Err(format!("unknown filter '{}'", filter.name));
+        };
+        if !arity.contains(&filter.args.len()) {
+            return Err(format!(
+                "wrong number of arguments ({}) for filter '{}'",
+                filter.args.len(),
+                filter.name
+            ));
+        }
+        for arg in &filter.args {
+            validate_term(arg)?;
+        }
+    }
+    Ok(())
+}
+
+fn validate_term(term: &Term) -> Result<(), String> {
+    let Term::Call { name, args } = term else {
+        return Ok(());
+    };
+    let Some(arity) = functions::function_arity(name) else {
+        return Err(format!("unknown function '{name}'"));
+    };
+    if !arity.contains(&args.len()) {
+        return Err(format!(
+            "wrong number of arguments ({}) for function '{name}'",
+            args.len()
+        ));
+    }
+    args.iter().try_for_each(validate_term)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_filters() {
+        let expression = parse(r#"token | default("a|b") | upper"#).unwrap();
+        assert_eq!(expression.head, Term::Variable("token".to_string()));
+        assert_eq!(expression.filters.len(), 2);
+        assert_eq!(
+            expression.filters[0].args,
+            vec![Term::Literal("a|b".to_string())]
+        );
+
+        assert!(validate("{{ name | upperr }}").is_err());
+        assert!(validate("{{ random_int(1) }}").is_err());
+        assert!(validate("{{#section}} {{ name | join(',') }}").is_ok());
+        assert!(validate(r"\{{ name | upperr }}").is_ok());
+
+        assert_eq!(
+            variables(r#"{{ token | default(env.TOKEN) }} {{ base64(user) }} \{{ skipped }}"#),
+            ["token | default(env.TOKEN)", "token", "env.TOKEN", "base64(user)", "user"]
+        );
+    }
+}
@@ -0,0 +1,105 @@
+// This file is part of alixt.
+// Copyright (C) 2025 Devon Harley Offutt
+//
+// alixt is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Encodes `form` and `multipart` request bodies.
+//!
+//! Bodies are encoded up front instead of through reqwest's multipart support, so they can be sent
+//! again when a 307 or 308 redirect is followed.
+
+use crate::models::plan::{FormPart, PartContent};
+
+/// application/x-www-form-urlencoded, spaces become `+`
+pub fn encode_form(fields: &[(String, String)]) -> Vec<u8> {
+    form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(fields)
+        .finish()
+        .into_bytes()
+}
+
+/// A fresh boundary, long and random enough not to occur inside any part
+pub fn boundary() -> String {
+    format!("alixt-{:016x}{:016x}", rand::random::<u64>(), rand::random::<u64>())
+}
+
+/// multipart/form-data, the parts in order. Text values are expected to be substituted already.
+pub fn encode_multipart(parts: &[FormPart], boundary: &str) -> Vec<u8> {
+    let mut body = Vec::new();
+    for part in parts {
+        body.extend_from_slice(format!("--{boundary}\r\n").as_bytes());
+        let mut disposition = format!("Content-Disposition: form-data; name=\"{}\"", escape(&part.name));
+        if let Some(filename) = &part.filename {
+            disposition.push_str(&format!("; filename=\"{}\"", escape(filename)));
+        }
+        body.extend_from_slice(disposition.as_bytes());
+        body.extend_from_slice(b"\r\n");
+        if let Some(content_type) = &part.content_type {
+            body.extend_from_slice(format!("Content-Type: {content_type}\r\n").as_bytes());
+        }
+        body.extend_from_slice(b"\r\n");
+        match &part.content {
+            PartContent::Text(text) => body.extend_from_slice(text.as_bytes()),
+            PartContent::File(bytes) => body.extend_from_slice(bytes),
+        }
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+    body
+}
+
+// quotes and line breaks are percent encoded in names and filenames, as browsers do
+fn escape(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_form_encoding() {
+        let fields = vec![
+            ("name".to_string(), "Ada Lovelace".to_string()),
+            ("note".to_string(), "a&b=c".to_string()),
+        ];
+        assert_eq!(encode_form(&fields), b"name=Ada+Lovelace&note=a%26b%3Dc");
+
+        let parts = vec![
+            FormPart {
+                name: "title".to_string(),
+                content: PartContent::Text("Report".to_string()),
+                filename: None,
+                content_type: None,
+            },
+            FormPart {
+                name: "file".to_string(),
+                content: PartContent::File(vec![0xff, 0x00]),
+                filename: Some("q\"1\".bin".to_string()),
+                content_type: Some("application/octet-stream".to_string()),
+            },
+        ];
+        let mut expected = b"--b\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nReport\r\n\
+            --b\r\nContent-Disposition: form-data; name=\"file\"; filename=\"q%221%22.bin\"\r\n\
+            Content-Type: application/octet-stream\r\n\r\n"
+            .to_vec();
+        expected.extend_from_slice(&[0xff, 0x00]);
+        expected.extend_from_slice(b"\r\n--b--\r\n");
+        assert_eq!(encode_multipart(&parts, "b"), expected);
+    }
+}
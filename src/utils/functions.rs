@@ -0,0 +1,187 @@
+// This file is part of alixt.
+// Copyright (C) 2025 Devon Harley Offutt
+//
+// alixt is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+use std::{
+    ops::RangeInclusive,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use rand::{Rng, distr::Alphanumeric, rngs::StdRng};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+// RFC 3986 unreserved characters are left as is
+const URL_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Functions whose output changes between calls, these get recorded in the request outcome so a
+/// failing run can be reproduced.
+pub fn is_generator(name: &str) -> bool {
+    matches!(
+        name,
+        "uuid" | "now" | "unix_ms" | "random_int" | "random_string"
+    )
+}
+
+/// The accepted argument counts of a template function, None if the function does not exist
+pub fn function_arity(name: &str) -> Option<RangeInclusive<usize>> {
+    match name {
+        "uuid" | "unix_ms" => Some(0..=0),
+        "now" => Some(0..=1),
+        "random_string" | "base64" | "sha256" | "urlencode" => Some(1..=1),
+        "random_int" => Some(2..=2),
+        _ => None,
+    }
+}
+
+/// The accepted argument counts of a filter, not counting the piped in value. Any single
+/// argument function can also be used as a filter, `{{ token | base64 }}`.
+pub fn filter_arity(name: &str) -> Option<RangeInclusive<usize>> {
+    match name {
+        "default" | "join" => Some(1..=1),
+        "upper" | "lower" | "trim" | "json_escape" => Some(0..=0),
+        name if function_arity(name) == Some(1..=1) => Some(0..=0),
+        _ => None,
+    }
+}
+
+pub fn call(name: &str, args: &[String], rng: &mut StdRng) -> Result<String, String> {
+    let Some(arity) = function_arity(name) else {
+        return Err(format!("unknown function '{name}'"));
+    };
+    if !arity.contains(&args.len()) {
+        return Err(format!(
+            "wrong number of arguments ({}) for function '{name}'",
+            args.len()
+        ));
+    }
+    match (name, args) {
+        ("uuid", _) => Ok(uuid_v4(rng)),
+        ("now", []) => now("rfc3339"),
+        ("now", [format, ..]) => now(format),
+        ("unix_ms", _) => Ok(unix_duration().as_millis().to_string()),
+        ("random_int", [min, max]) => {
+            let min = parse_int(min)?;
+            let max = parse_int(max)?;
+            if min > max {
+                return Err(format!("random_int: min {min} is greater than max {max}"));
+            }
+            Ok(rng.random_range(min..=max).to_string())
+        }
+        ("random_string", [len]) => {
+            let len = parse_int(len)?;
+            let len = usize::try_from(len)
+                .map_err(|_| format!("random_string: invalid length {len}"))?;
+            Ok((0..len).map(|_| rng.sample(Alphanumeric) as char).collect())
+        }
+        ("base64", [value]) => Ok(STANDARD.encode(value)),
+        ("sha256", [value]) => Ok(Sha256::digest(value)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()),
+        ("urlencode", [value]) => Ok(utf8_percent_encode(value, URL_ENCODE_SET).to_string()),
+        _ => Err(format!("unknown function '{name}'")),
+    }
+}
+
+/// Applies every filter except `default`, which the evaluator handles since it is the only one
+/// that runs on a missing value.
+pub fn apply_filter(
+    name: &str,
+    value: String,
+    args: &[String],
+    rng: &mut StdRng,
+) -> Result<String, String> {
+    let Some(arity) = filter_arity(name) else {
+        return Err(format!("unknown filter '{name}'"));
+    };
+    if !arity.contains(&args.len()) {
+        return Err(format!(
+            "wrong number of arguments ({}) for filter '{name}'",
+            args.len()
+        ));
+    }
+    match (name, args) {
+        ("upper", _) => Ok(value.to_uppercase()),
+        ("lower", _) => Ok(value.to_lowercase()),
+        ("trim", _) => Ok(value.trim().to_string()),
+        ("json_escape", _) => {
+            let quoted = Value::String(value).to_string();
+            Ok(quoted[1..quoted.len() - 1].to_string())
+        }
+        ("join", [separator]) => {
+            let Ok(Value::Array(items)) = serde_json::from_str::<Value>(&value) else {
+                return Err(format!("join: '{value}' is not a JSON array"));
+            };
+            Ok(items
+                .iter()
+                .map(|item| match item {
+                    Value::String(s) => s.clone(),
+                    v => v.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(separator))
+        }
+        (name, _) => call(name, &[value], rng),
+    }
+}
+
+fn parse_int(value: &str) -> Result<i64, String> {
+    value
+        .trim()
+        .parse::<i64>()
+        .map_err(|_| format!("expected an integer, got '{value}'"))
+}
+
+fn unix_duration() -> std::time::Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+fn now(format: &str) -> Result<String, String> {
+    let time = SystemTime::now();
+    match format {
+        "rfc3339" => Ok(humantime::format_rfc3339_seconds(time).to_string()),
+        "rfc3339_ms" => Ok(humantime::format_rfc3339_millis(time).to_string()),
+        "unix" => Ok(unix_duration().as_secs().to_string()),
+        "unix_ms" => Ok(unix_duration().as_millis().to_string()),
+        other => Err(format!(
+            "unknown time format '{other}', expected one of rfc3339, rfc3339_ms, unix, unix_ms"
+        )),
+    }
+}
+
+fn uuid_v4(rng: &mut StdRng) -> String {
+    let mut bytes: [u8; 16] = rng.random();
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
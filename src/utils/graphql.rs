@@ -0,0 +1,138 @@
+// This file is part of alixt.
+// Copyright (C) 2025 Devon Harley Offutt
+//
+// alixt is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! GraphQL request bodies, and the assertions on the `errors` array of a response.
+
+use regex::Regex;
+use serde_json::{Map, Value};
+
+use crate::{
+    models::{
+        plan::GraphQlOperation,
+        test_data::{AssertionOutcome, FailureType},
+    },
+    utils::json,
+};
+
+/// The JSON body of `operation`, with `substitute` applied to the query, the operation name and
+/// every string inside the variables
+pub fn request_body(
+    operation: GraphQlOperation,
+    mut substitute: impl FnMut(String) -> String,
+) -> Value {
+    let mut body = Map::new();
+    body.insert("query".to_string(), Value::String(substitute(operation.query)));
+    if let Some(variables) = operation.variables {
+        let mut variables = Value::Object(variables.into_iter().collect());
+        json::map_strings(&mut variables, &mut substitute);
+        body.insert("variables".to_string(), variables);
+    }
+    if let Some(name) = operation.operation_name {
+        body.insert("operationName".to_string(), Value::String(substitute(name)));
+    }
+    Value::Object(body)
+}
+
+/// Checks the `errors` array of a GraphQL response. `pattern` has its placeholders substituted.
+pub fn check_graphql(
+    json: Option<&Value>,
+    no_errors: bool,
+    pattern: Option<&str>,
+    outcome: &mut AssertionOutcome,
+) {
+    if !no_errors && pattern.is_none() {
+        return;
+    }
+    let Some(json) = json else {
+        outcome.push(FailureType::InvalidJson());
+        return;
+    };
+    let messages: Vec<String> = json
+        .get("errors")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .map(|error| match error.get("message") {
+            Some(Value::String(message)) => message.clone(),
+            _ => error.to_string(),
+        })
+        .collect();
+
+    if no_errors && !messages.is_empty() {
+        outcome.push(FailureType::GraphqlErrors {
+            messages: messages.clone(),
+        });
+    }
+    if let Some(pattern) = pattern {
+        let matched = match Regex::new(pattern) {
+            Ok(regex) => messages.iter().any(|message| regex.is_match(message)),
+            Err(_) => {
+                outcome.push(FailureType::GraphqlErrorMismatch {
+                    pattern: pattern.to_string(),
+                    messages: vec!["Invalid Regex Syntax".to_string()],
+                });
+                return;
+            }
+        };
+        if !matched {
+            outcome.push(FailureType::GraphqlErrorMismatch {
+                pattern: pattern.to_string(),
+                messages,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_graphql_body_and_errors() {
+        let operation = GraphQlOperation {
+            query: "query User($id: ID!) { user(id: $id) { name } }".to_string(),
+            variables: Some([("id".to_string(), json!("{{id}}")), ("limit".to_string(), json!(5))].into()),
+            operation_name: Some("User".to_string()),
+        };
+        let body = request_body(operation, |text| text.replace("{{id}}", "u-1"));
+        assert_eq!(
+            body,
+            json!({
+                "query": "query User($id: ID!) { user(id: $id) { name } }",
+                "variables": { "id": "u-1", "limit": 5 },
+                "operationName": "User",
+            })
+        );
+
+        let ok = json!({ "data": { "user": { "name": "Ada" } } });
+        let mut outcome = AssertionOutcome::Passed;
+        check_graphql(Some(&ok), true, None, &mut outcome);
+        assert!(outcome.is_passing());
+
+        let failed = json!({ "data": null, "errors": [{ "message": "User u-2 not found" }] });
+        let mut outcome = AssertionOutcome::Passed;
+        check_graphql(Some(&failed), false, Some("not found$"), &mut outcome);
+        assert!(outcome.is_passing());
+        check_graphql(Some(&failed), true, Some("^Forbidden"), &mut outcome);
+        let AssertionOutcome::Failed(failures) = outcome else {
+            panic!("expected failures, got {outcome:?}");
+        };
+        assert!(matches!(&failures[0], FailureType::GraphqlErrors { messages } if messages.len() == 1));
+        assert!(matches!(&failures[1], FailureType::GraphqlErrorMismatch { .. }));
+    }
+}
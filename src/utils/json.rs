@@ -0,0 +1,41 @@
+// This file is part of alixt.
+// Copyright (C) 2025 Devon Harley Offutt
+//
+// alixt is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! Helpers for walking the strings nested in a JSON value, e.g. the placeholders of GraphQL
+//! variables and JSON-RPC params.
+
+use serde_json::Value;
+
+/// Applies `f` to every string nested in `value`, used to substitute placeholders in JSON bodies
+pub fn map_strings(value: &mut Value, f: &mut impl FnMut(String) -> String) {
+    match value {
+        Value::String(text) => *text = f(std::mem::take(text)),
+        Value::Array(items) => items.iter_mut().for_each(|item| map_strings(item, f)),
+        Value::Object(map) => map.values_mut().for_each(|item| map_strings(item, f)),
+        _ => {}
+    }
+}
+
+/// Every string nested in `value`, used to check their placeholders when the plan is built
+pub fn strings(value: &Value) -> Vec<&String> {
+    match value {
+        Value::String(text) => vec![text],
+        Value::Array(items) => items.iter().flat_map(strings).collect(),
+        Value::Object(map) => map.values().flat_map(strings).collect(),
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,181 @@
+// This file is part of alixt.
+// Copyright (C) 2025 Devon Harley Offutt
+//
+// alixt is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <http://www.gnu.org/licenses/>.
+
+
+//! JSON-RPC 2.0 envelopes, and the check that every response answers the call it belongs to.
+
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use crate::{
+    models::{config::JsonRpc, test_data::FailureType},
+    utils::json,
+};
+
+/// The envelope of every call, `ids` holds one entry per call, `None` for notifications
+pub fn request_body(
+    rpc: &JsonRpc,
+    ids: &[Option<u64>],
+    mut substitute: impl FnMut(String) -> String,
+) -> Value {
+    let mut envelopes = rpc.calls().iter().zip(ids).map(|(call, id)| {
+        let mut envelope = Map::new();
+        envelope.insert("jsonrpc".to_string(), Value::from("2.0"));
+        envelope.insert("method".to_string(), Value::String(substitute(call.method.clone())));
+        if let Some(params) = &call.params {
+            let mut params = params.clone();
+            json::map_strings(&mut params, &mut substitute);
+            envelope.insert("params".to_string(), params);
+        }
+        if let Some(id) = id {
+            envelope.insert("id".to_string(), Value::from(*id));
+        }
+        Value::Object(envelope)
+    });
+    match rpc {
+        JsonRpc::Call(_) => envelopes.next().unwrap_or_default(),
+        JsonRpc::Batch(_) => Value::Array(envelopes.collect()),
+    }
+}
+
+/// Checks the ids of the response against the ones that were sent. The responses of a batch may
+/// come in any order, so they are returned in the order of the calls, with `null` in the place of
+/// notifications and of missing responses.
+pub fn match_responses(
+    rpc: &JsonRpc,
+    ids: &[Option<u64>],
+    json: Option<Value>,
+    failures: &mut Vec<FailureType>,
+) -> Option<Value> {
+    let mut mismatch = |expected: String, found: String| {
+        failures.push(FailureType::JsonRpcIdMismatch { expected, found })
+    };
+    match rpc {
+        JsonRpc::Call(_) => {
+            let Some(Some(id)) = ids.first() else {
+                return json;
+            };
+            let found = json.as_ref().and_then(|json| json.get("id"));
+            if found != Some(&Value::from(*id)) {
+                mismatch(id.to_string(), describe(found));
+            }
+            json
+        }
+        JsonRpc::Batch(_) => {
+            let Some(Value::Array(responses)) = json else {
+                if ids.iter().any(Option::is_some) {
+                    mismatch("a batch response".to_string(), describe(json.as_ref()));
+                }
+                return json;
+            };
+            // compared as JSON values, so `"1"` does not answer the call with the id 1
+            let mut by_id: HashMap<u64, Value> = HashMap::new();
+            for response in responses {
+                let found = response.get("id");
+                let Some(sent) = ids
+                    .iter()
+                    .flatten()
+                    .find(|sent| found == Some(&Value::from(**sent)))
+                else {
+                    mismatch("one of the sent ids".to_string(), describe(found));
+                    continue;
+                };
+                by_id.insert(*sent, response);
+            }
+            let ordered = ids
+                .iter()
+                .map(|id| match id {
+                    Some(id) => by_id.remove(id).unwrap_or_else(|| {
+                        mismatch(id.to_string(), "no response".to_string());
+                        Value::Null
+                    }),
+                    None => Value::Null,
+                })
+                .collect();
+            Some(Value::Array(ordered))
+        }
+    }
+}
+
+// strings keep their quotes, so an id of "1" is told apart from 1
+fn describe(id: Option<&Value>) -> String {
+    match id {
+        Some(id) => id.to_string(),
+        None => "no id".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_batch_ids() {
+        let rpc: JsonRpc = serde_json::from_value(json!([
+            { "method": "eth_blockNumber" },
+            { "method": "log", "params": ["{{id}}"], "notification": true },
+            { "method": "eth_getBalance", "params": ["0xabc", "latest"] },
+        ]))
+        .unwrap();
+        let ids = [Some(1), None, Some(2)];
+        let body = request_body(&rpc, &ids, |text| text.replace("{{id}}", "7"));
+        assert_eq!(body[1], json!({ "jsonrpc": "2.0", "method": "log", "params": ["7"] }));
+        assert_eq!(body[2]["id"], json!(2));
+
+        let mut failures = Vec::new();
+        let response = json!([
+            { "jsonrpc": "2.0", "id": 2, "result": "0x10" },
+            { "jsonrpc": "2.0", "id": 1, "result": "0x5" },
+        ]);
+        let ordered = match_responses(&rpc, &ids, Some(response), &mut failures).unwrap();
+        assert!(failures.is_empty());
+        assert_eq!(ordered, json!([
+            { "jsonrpc": "2.0", "id": 1, "result": "0x5" },
+            null,
+            { "jsonrpc": "2.0", "id": 2, "result": "0x10" },
+        ]));
+
+        let response = json!([{ "jsonrpc": "2.0", "id": 9, "result": null }]);
+        match_responses(&rpc, &ids, Some(response), &mut failures);
+        assert_eq!(failures.len(), 3);
+
+        // an id is only answered by the same JSON value
+        let mut failures = Vec::new();
+        let response = json!([
+            { "jsonrpc": "2.0", "id": "1", "result": "0x5" },
+            { "jsonrpc": "2.0", "id": 2, "result": "0x10" },
+        ]);
+        let ordered = match_responses(&rpc, &ids, Some(response), &mut failures).unwrap();
+        assert_eq!(ordered[0], Value::Null);
+        assert_eq!(ordered[2]["result"], "0x10");
+        let found: Vec<_> = failures
+            .iter()
+            .map(|failure| match failure {
+                FailureType::JsonRpcIdMismatch { expected, found } => (expected.as_str(), found.as_str()),
+                other => panic!("unexpected failure {other:?}"),
+            })
+            .collect();
+        assert_eq!(found, [("one of the sent ids", "\"1\""), ("1", "no response")]);
+
+        let call: JsonRpc = serde_json::from_value(json!({ "method": "eth_blockNumber" })).unwrap();
+        let mut failures = Vec::new();
+        let response = json!({ "jsonrpc": "2.0", "id": "3", "result": "0x5" });
+        match_responses(&call, &[Some(3)], Some(response), &mut failures);
+        assert_eq!(failures.len(), 1);
+    }
+}
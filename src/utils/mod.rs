@@ -17,3 +17,13 @@
 
 pub mod template;
 pub mod env;
+pub mod condition;
+pub mod data;
+pub mod expression;
+pub mod functions;
+pub mod cache;
+pub mod cookies;
+pub mod form;
+pub mod graphql;
+pub mod jsonrpc;
+pub mod json;
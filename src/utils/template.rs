@@ -45,6 +45,10 @@ env_file = "./secrets/.env"
     [capture.request.capture]
     forgejo_version = "/version"
 
+# static values, usable anywhere as {{name}} or {{global.name}}
+[vars]
+expected_version = "13.0.2+gitea-1.22.0"
+
 [[run]]
 name = "Example Test Configuration"
 method = "get"
@@ -69,7 +73,7 @@ port = 7878
     subset_includes = ["/version"]
 
     [run.request.assert.body_matches]
-    "/version" = "13.0.2+gitea-1.22.0"
+    "/version" = "{{expected_version}}"
 
     [run.request.assert.subset_matches]
     "/version" = "{{forgejo_version}}"
@@ -85,11 +89,15 @@ port = 7878
   body = """
   {
       "name": "Doug Walker",
-      "username": "digdug",
+      "username": "{{request.username}}",
       "password": "password123",
       "email": "exapmle@example.com"
   }
   """
+
+    # request level vars, also reachable as {{request.name}}
+    [run.request.vars]
+    username = "digdug"
 "#;
 
 
@@ -116,7 +124,10 @@ pub fn generate<W: Write>(writer: &mut W) -> Result<(), AlixtError> {
         port: Some(7878),
         path: Some("/api/v1/version".to_string()),
         body: None,
+        raw: None,
         capture: None,
+        assert: None,
+        cache: None,
     };
     let mut capture_headers = HashMap::<String, String>::new();
     capture_headers.insert("Accept".to_string(), "application/json".to_string());
@@ -160,8 +171,25 @@ pub fn generate<W: Write>(writer: &mut W) -> Result<(), AlixtError> {
         scheme: Some(Scheme::Http),
         host: Some("{{host}}".to_string()),
         port: Some(7878),
+        unix_socket: None,
         path: None,
         body: None,
+        body_file: None,
+        raw: None,
+        vars: None,
+        skip_if: None,
+        only_if: None,
+        data: None,
+        data_file: None,
+        depends_on: None,
+        exports: None,
+        cookies: None,
+        seed_cookies: None,
+        follow_redirects: None,
+        ca_cert: None,
+        client_cert: None,
+        client_key: None,
+        resolve: None,
         request: vec![
             Request {
                 name: "Confirm Forgejo Version".to_string(),
@@ -170,9 +198,26 @@ pub fn generate<W: Write>(writer: &mut W) -> Result<(), AlixtError> {
                 scheme: Some(Scheme::Https),
                 host: None,
                 port: None,
+                unix_socket: None,
                 path: Some("/api/v1/version".to_string()),
                 body: None,
+                body_file: None,
+                form: None,
+                multipart: None,
+                graphql: None,
+                jsonrpc: None,
+                stream: None,
+                sse: None,
+                raw: None,
+                vars: None,
+                skip_if: None,
+                only_if: None,
+                data: None,
+                data_file: None,
+                foreach: None,
+                follow_redirects: None,
                 capture: None,
+                export: None,
                 assert: Some(Assert {
                     breaking: true,
                     status: Some(200),
@@ -180,6 +225,17 @@ pub fn generate<W: Write>(writer: &mut W) -> Result<(), AlixtError> {
                     subset_matches: Some(subset_matches),
                     subset_includes: Some(vec!["/version".to_string()]),
                     subset_regex: Some(subset_regex),
+                    cookies: None,
+                    redirect_count: None,
+                    final_url: None,
+                    location: None,
+                    tls: None,
+                    graphql_no_errors: None,
+                    graphql_error_matches: None,
+                    jsonrpc_result: None,
+                    jsonrpc_error_code: None,
+                    sse_event_count: None,
+                    sse_events: None,
                 }),
             },
             Request {
@@ -189,12 +245,30 @@ pub fn generate<W: Write>(writer: &mut W) -> Result<(), AlixtError> {
                 scheme: Some(Scheme::Https),
                 host: None,
                 port: None,
+                unix_socket: None,
                 path: Some("/api".to_string()),
                 body: Some(request_body.to_string()),
+                body_file: None,
+                form: None,
+                multipart: None,
+                graphql: None,
+                jsonrpc: None,
+                stream: None,
+                sse: None,
+                raw: None,
+                vars: None,
+                skip_if: None,
+                only_if: None,
+                data: None,
+                data_file: None,
+                foreach: None,
+                follow_redirects: None,
                 capture: None,
+                export: None,
                 assert: None,
             },
         ],
+        teardown: None,
     };
 
     let mut environment_variables = HashMap::new();
@@ -220,7 +294,18 @@ pub fn generate<W: Write>(writer: &mut W) -> Result<(), AlixtError> {
                 }
             ]),
         }*/capture),
+        vars: None,
+        setup: None,
         run: vec![login_run],
+        teardown: None,
+        cookies: None,
+        seed_cookies: None,
+        follow_redirects: None,
+        ca_cert: None,
+        client_cert: None,
+        client_key: None,
+        resolve: None,
+        proxy: None,
     };
 
     let toml_string = toml::to_string_pretty(&config)?;